@@ -1,100 +1,623 @@
+use chrono::prelude::*;
 use hashbrown::HashMap;
 use rust_decimal::prelude::*;
 use anyhow::anyhow;
 
+use crate::bar::{Bar, BarFill};
+use crate::financing::{Accrual, AccrualKind, Financing};
+use crate::fx;
+use crate::instrument::Instruments;
+use crate::margin::{MarginModel, ShortProceeds};
 use crate::position::Position;
+use crate::Tick;
 
-/// `OrderState` tracks if an order is `Pending`,
-/// `Rejected`, or has been `Executed`.
+/// `OrderId` identifies an order for as long as the account exists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OrderId(pub u64);
+
+/// `OrderState` tracks if an order is `Pending`, `PartiallyFilled`,
+/// `Rejected`, `Cancelled`, or has been `Executed`.
 #[derive(Debug, Clone, PartialEq)]
 pub enum OrderState {
     Pending,
+    PartiallyFilled,
     Rejected,
+    Cancelled,
     Executed,
 }
 
+/// `OrderType` decides when a pending order can be filled. `Market` orders
+/// fill at the touch on the next tick for their asset. `Limit` and `Stop`
+/// orders rest until the touch crosses their price, and a `StopLimit` order
+/// becomes a `Limit` order once its `stop` has been touched.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderType {
+    Market,
+    Limit(Decimal),
+    Stop(Decimal),
+    StopLimit { stop: Decimal, limit: Decimal },
+}
+
 /// `Order` tracks in progres or recently executed
-/// orders.
+/// orders. An order cannot fill on a tick before `not_before_index`, or
+/// with a timestamp before `not_before_time`. `reject_reason` says why a
+/// `Rejected` order was rejected.
 #[derive(Debug, Clone)]
 pub struct Order {
+    pub id: OrderId,
     pub state: OrderState,
     pub asset: String,
     pub lots: isize,
+    pub order_type: OrderType,
+    pub filled_lots: isize,
     pub cost_basis: Option<Decimal>,
+    pub not_before_index: i64,
+    pub not_before_time: Option<DateTime<Utc>>,
+    pub reject_reason: Option<String>,
+}
+
+impl Order {
+    /// `is_open` is true while the order can still be filled, cancelled or modified.
+    pub fn is_open(&self) -> bool {
+        self.state == OrderState::Pending || self.state == OrderState::PartiallyFilled
+    }
+
+    /// `remaining` is the signed number of lots still to be filled.
+    pub fn remaining(&self) -> isize {
+        self.lots - self.filled_lots
+    }
+
+    /// `record_fill` adds `lots` filled at `price` to this order, keeping
+    /// `cost_basis` as the average fill price and updating `state`.
+    pub fn record_fill(&mut self, lots: isize, price: Decimal) {
+        let filled = Decimal::new(self.filled_lots as i64, 0);
+        let total = self.cost_basis.unwrap_or_default() * filled + price * Decimal::new(lots as i64, 0);
+        self.filled_lots += lots;
+        self.cost_basis = Some(total / Decimal::new(self.filled_lots as i64, 0));
+        self.state = if self.filled_lots == self.lots {
+            OrderState::Executed
+        } else {
+            OrderState::PartiallyFilled
+        };
+    }
+
+    /// `evaluate` checks this order against `tick` and returns the price it
+    /// would fill at, if any. Buys are checked against the ask and sells
+    /// against the bid. A triggered `StopLimit` is turned into a `Limit`.
+    pub fn evaluate(&mut self, tick: &Tick) -> Option<Decimal> {
+        if tick.asset != self.asset || self.lots == 0 {
+            return None;
+        }
+        let buy = self.lots > 0;
+        let touch = if buy { tick.ask } else { tick.bid };
+
+        if let OrderType::StopLimit { stop, limit } = self.order_type {
+            if (buy && touch >= stop) || (!buy && touch <= stop) {
+                self.order_type = OrderType::Limit(limit);
+            }
+        }
+
+        let fills = match self.order_type {
+            OrderType::Market => true,
+            OrderType::Limit(p) => (buy && touch <= p) || (!buy && touch >= p),
+            OrderType::Stop(p) => (buy && touch >= p) || (!buy && touch <= p),
+            OrderType::StopLimit { .. } => false,
+        };
+        if fills {
+            Some(touch)
+        } else {
+            None
+        }
+    }
+
+    /// `evaluate_bar` checks this order against `bar` and returns the price
+    /// it would fill at, if any. Market orders fill at the price `fill`
+    /// picks. Limits and stops fill if the bar's high or low reaches them,
+    /// at their price or at the open if the bar opened through it. A
+    /// triggered `StopLimit` is turned into a `Limit`, and only fills in the
+    /// same bar if the trigger price is within its limit.
+    pub fn evaluate_bar(&mut self, bar: &Bar, fill: &BarFill) -> Option<Decimal> {
+        if bar.asset != self.asset || self.lots == 0 {
+            return None;
+        }
+        let buy = self.lots > 0;
+
+        if let OrderType::StopLimit { stop, limit } = self.order_type {
+            if (buy && bar.high >= stop) || (!buy && bar.low <= stop) {
+                self.order_type = OrderType::Limit(limit);
+                let trigger = if buy { bar.open.max(stop) } else { bar.open.min(stop) };
+                let within = (buy && trigger <= limit) || (!buy && trigger >= limit);
+                return if within { Some(trigger) } else { None };
+            }
+        }
+
+        match self.order_type {
+            OrderType::Market => Some(match fill {
+                BarFill::Open => bar.open,
+                BarFill::Close => bar.close,
+            }),
+            OrderType::Limit(p) if buy && bar.low <= p => Some(bar.open.min(p)),
+            OrderType::Limit(p) if !buy && bar.high >= p => Some(bar.open.max(p)),
+            OrderType::Stop(p) if buy && bar.high >= p => Some(bar.open.max(p)),
+            OrderType::Stop(p) if !buy && bar.low <= p => Some(bar.open.min(p)),
+            _ => None,
+        }
+    }
+}
+
+/// `Fill` records some or all of an order being executed.
+#[derive(Debug, Clone)]
+pub struct Fill {
+    pub order_id: OrderId,
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub lots: isize,
+    pub price: Decimal,
+    pub fee: Decimal,
 }
 
 /// `Account` tracks the current state of an account,
-/// including remaining `cash`, any `Position`s open,
-/// a history of `trade`s, and any pending or recently executed
-/// `orders`.
+/// including remaining `cash` in the base currency, which is fixed when the
+/// account is made, `balances` of cash in any other currency, any
+/// `Position`s open, a history of `trade`s, any pending or recently executed
+/// `orders`, `pending_fills` that have not yet been applied, a ledger of
+/// every `fills` that has been applied, the total `fees_paid` in the base
+/// currency, converted at the time of each fill,
+/// `realized_pnl` per asset, net of fees, including closed positions, the
+/// `margin` model, any `short_collateral` held per asset, orders that
+/// were `rejected`, `accruals` of interest and borrow fees, and the
+/// `instruments` traded, which set contract multipliers, tick and lot sizes
+/// and the currency each asset settles in. `realized_pnl`, `short_collateral`
+/// and borrow fees are in the asset's currency.
 #[derive(Debug, Clone)]
 pub struct Account {
     pub cash: Decimal,
+    base_currency: String,
+    pub balances: HashMap<String, Decimal>,
     pub portfolio: HashMap<String, Position>,
     pub trades: Vec<Position>,
     pub orders: Vec<Order>,
+    pub pending_fills: Vec<Fill>,
+    pub fills: Vec<Fill>,
+    pub fees_paid: Decimal,
+    pub realized_pnl: HashMap<String, Decimal>,
+    pub margin: MarginModel,
+    pub short_collateral: HashMap<String, Decimal>,
+    pub rejected: Vec<Order>,
+    pub accruals: Vec<Accrual>,
+    pub instruments: Instruments,
+    next_order_id: u64,
 }
 
 impl Account {
+    pub fn new(cash: Decimal) -> Self {
+        Self::with_base_currency(cash, "USD")
+    }
+
+    /// `with_base_currency` makes an account holding `cash` in `currency`,
+    /// which equity and margin are then measured in.
+    pub fn with_base_currency(cash: Decimal, currency: &str) -> Self {
+        Self {
+            cash,
+            base_currency: currency.to_string(),
+            balances: HashMap::new(),
+            portfolio: HashMap::new(),
+            trades: vec![],
+            orders: vec![],
+            pending_fills: vec![],
+            fills: vec![],
+            fees_paid: Decimal::new(0, 0),
+            realized_pnl: HashMap::new(),
+            margin: MarginModel::default(),
+            short_collateral: HashMap::new(),
+            rejected: vec![],
+            accruals: vec![],
+            instruments: Instruments::default(),
+            next_order_id: 1,
+        }
+    }
+
+    pub fn base_currency(&self) -> &str {
+        &self.base_currency
+    }
 
-    pub fn submit_order(self: &mut Self, asset: String, lots: isize) {
-        let order = Order{state: OrderState::Pending, asset, lots, cost_basis: None};
+    /// `submit_order` adds a pending order and returns its id. Orders that do
+    /// not fit the asset's lot or tick size are rounded or rejected,
+    /// according to `instruments.tick_policy`, and orders that would go
+    /// short an asset that is not shortable, counting fills not yet applied
+    /// and what open sell orders still have to fill, are rejected.
+    pub fn submit_order(self: &mut Self, asset: String, lots: isize, order_type: OrderType) -> OrderId {
+        let id = OrderId(self.next_order_id);
+        self.next_order_id += 1;
+        let held = self.portfolio.get(&asset).map(|p| p.lots).unwrap_or(0)
+            + self.pending_fills.iter().filter(|f| f.asset == asset).map(|f| f.lots).sum::<isize>();
+        let selling: isize = self.orders.iter()
+            .filter(|o| o.asset == asset && o.is_open())
+            .map(|o| o.remaining().min(0))
+            .sum();
+        let (lots, order_type, state, reject_reason) = match self.instruments.conform(&asset, lots, &order_type) {
+            Ok((lots, _)) if lots < 0 && held + selling + lots < 0 && !self.instruments.is_shortable(&asset) => {
+                let reason = format!("{} is not shortable", asset);
+                (lots, order_type, OrderState::Rejected, Some(reason))
+            }
+            Ok((lots, order_type)) => (lots, order_type, OrderState::Pending, None),
+            Err(reason) => (lots, order_type, OrderState::Rejected, Some(reason)),
+        };
+        let order = Order{id, state, asset, lots, order_type, filled_lots: 0, cost_basis: None, not_before_index: 0, not_before_time: None, reject_reason};
         self.orders.push(order);
+        id
+    }
+
+    pub fn order(&self, id: OrderId) -> Option<&Order> {
+        self.orders.iter().find(|o| o.id == id)
+    }
+
+    pub fn order_mut(&mut self, id: OrderId) -> Option<&mut Order> {
+        self.orders.iter_mut().find(|o| o.id == id)
+    }
+
+    fn open_order_mut(&mut self, id: OrderId) -> anyhow::Result<&mut Order> {
+        match self.order_mut(id) {
+            Some(order) if order.is_open() => Ok(order),
+            Some(order) => Err(anyhow!("Order {:?} is {:?} and can no longer be changed", id, order.state)),
+            None => Err(anyhow!("No order with id {:?}", id)),
+        }
+    }
+
+    /// `cancel_order` cancels whatever is left of an open order. Lots that
+    /// have already been filled are kept.
+    pub fn cancel_order(&mut self, id: OrderId) -> anyhow::Result<()> {
+        let order = self.open_order_mut(id)?;
+        order.state = OrderState::Cancelled;
+        Ok(())
+    }
+
+    /// `modify_order` changes the size and type of an open order. The new
+    /// size must be on the same side and no smaller than what has already
+    /// been filled, and must fit the asset's lot and tick size.
+    pub fn modify_order(&mut self, id: OrderId, lots: isize, order_type: OrderType) -> anyhow::Result<()> {
+        let asset = self.open_order_mut(id)?.asset.clone();
+        let (lots, order_type) = self.instruments.conform(&asset, lots, &order_type).map_err(|e| anyhow!(e))?;
+        let order = self.open_order_mut(id)?;
+        if lots == 0 || lots.signum() != order.lots.signum() {
+            return Err(anyhow!("Cannot change the side of order {:?}", id));
+        }
+        if lots.abs() < order.filled_lots.abs() {
+            return Err(anyhow!("Order {:?} already has {} lots filled", id, order.filled_lots));
+        }
+        order.lots = lots;
+        order.order_type = order_type;
+        if order.filled_lots == lots {
+            order.state = OrderState::Executed;
+        }
+        Ok(())
+    }
+
+    /// `reject_fill` rejects the order `fill` came from with `reason`,
+    /// taking the fill back out of its `filled_lots` and `cost_basis`.
+    pub fn reject_fill(&mut self, fill: &Fill, reason: String) {
+        if let Some(order) = self.order_mut(fill.order_id) {
+            let filled = order.filled_lots - fill.lots;
+            order.cost_basis = match order.cost_basis {
+                Some(cost) if filled != 0 => {
+                    let total = cost * Decimal::new(order.filled_lots as i64, 0) - fill.price * Decimal::new(fill.lots as i64, 0);
+                    Some(total / Decimal::new(filled as i64, 0))
+                }
+                _ => None,
+            };
+            order.filled_lots = filled;
+        }
+        self.reject_order(fill.order_id, reason);
+    }
+
+    /// `reject_order` marks an order `Rejected` with `reason`.
+    pub fn reject_order(&mut self, id: OrderId, reason: String) {
+        if let Some(order) = self.order_mut(id) {
+            order.state = OrderState::Rejected;
+            order.reject_reason = Some(reason);
+        }
     }
 
     pub fn clear_executed(self: &mut Self) {
         for i in (0..self.orders.len()).rev() {
-            if self.orders[i].state == OrderState::Executed {
+            let state = &self.orders[i].state;
+            if *state == OrderState::Executed || *state == OrderState::Cancelled {
                 self.orders.remove(i);
             }
         }
     }
 
-    pub fn position(self: &mut Self, p: Position) -> anyhow::Result<()> {
-        let response = self._position(p)?;
+    /// `apply_fill` opens, adds to or closes a position for `fill`, deducts
+    /// its fee from cash in the asset's currency and records it in the
+    /// `fills` ledger. `prices` are used to mark other positions when
+    /// checking margin, and must hold an FX rate for the asset's currency.
+    /// Fills that would take an asset that is not shortable short fail.
+    pub fn apply_fill(&mut self, fill: &Fill, prices: &HashMap<String, Decimal>) -> anyhow::Result<()> {
+        let held = self.portfolio.get(&fill.asset).map(|p| p.lots).unwrap_or(0);
+        if fill.lots < 0 && held + fill.lots < 0 && !self.instruments.is_shortable(&fill.asset) {
+            return Err(anyhow!("{} is not shortable", fill.asset));
+        }
+        let currency = self.instruments.currency(&fill.asset);
+        let fee = self.to_base(fill.fee, &currency, prices).map_err(|e| anyhow!("{} for {}", e, fill.asset))?;
+        let p = Position{asset: fill.asset.clone(), lots: fill.lots, cost_basis: fill.price};
+        self.check_margin(&p, fill.fee, prices)?;
+        self.book(p);
+        self.credit(&fill.asset, -fill.fee);
+        self.fees_paid += fee;
+        *self.realized_pnl.entry(fill.asset.clone()).or_default() -= fill.fee;
+        self.fills.push(fill.clone());
+        Ok(())
+    }
+
+    /// `accrue` posts `days` of interest on the cash held in every currency
+    /// and borrow fees on short positions, marked at `prices`, to cash and
+    /// the `accruals` ledger. Each is charged in the currency it accrues in,
+    /// and borrow fees also count against the asset's realized P&L. Ledger
+    /// entries are converted to the base currency at `prices`, and nothing is
+    /// posted if there is no FX rate to do so.
+    pub fn accrue(&mut self, financing: &Financing, days: i64, prices: &HashMap<String, Decimal>, timestamp: DateTime<Utc>) -> anyhow::Result<()> {
+        if days <= 0 || financing.days_per_year.is_zero() {
+            return Ok(());
+        }
+        let accrued = |balance: Decimal, rate: Decimal| balance * rate * Decimal::new(days, 0) / financing.days_per_year;
+        let mut entries = vec![];
 
-        match response {
-            Some(asset) => {
-                self.portfolio.remove(&asset);
-            },
-            None => {},
-        };
+        let mut balances: Vec<(String, Decimal)> = self.balances.iter().map(|(c, b)| (c.clone(), *b)).collect();
+        balances.sort_by(|a, b| a.0.cmp(&b.0));
+        balances.insert(0, (self.base_currency.clone(), self.cash));
+        for (currency, balance) in balances {
+            let (kind, rate) = if balance.is_sign_negative() {
+                (AccrualKind::DebitInterest, financing.debit_rate)
+            } else {
+                (AccrualKind::CreditInterest, financing.credit_rate)
+            };
+            entries.push((kind, currency, accrued(balance, rate)));
+        }
+
+        let mut shorts: Vec<&Position> = self.portfolio.values().filter(|p| p.lots < 0).collect();
+        shorts.sort_by(|a, b| a.asset.cmp(&b.asset));
+        for pos in shorts {
+            let value = self.instruments.notional(&pos.asset, pos.lots, Self::mark(pos, prices));
+            let fee = accrued(value, financing.borrow_rate(&pos.asset));
+            entries.push((AccrualKind::BorrowFee(pos.asset.clone()), self.instruments.currency(&pos.asset), fee));
+        }
+
+        let mut posted = vec![];
+        for (kind, currency, amount) in entries {
+            if !amount.is_zero() {
+                let base = self.to_base(amount, &currency, prices)?;
+                posted.push((kind, currency, amount, base));
+            }
+        }
+        for (kind, currency, amount, base) in posted {
+            if let AccrualKind::BorrowFee(asset) = &kind {
+                *self.realized_pnl.entry(asset.clone()).or_default() += amount;
+            }
+            self.deposit(&currency, amount);
+            self.accruals.push(Accrual{timestamp, kind, days, amount: base});
+        }
         Ok(())
     }
 
-    fn _position(self: &mut Self, p: Position) -> anyhow::Result<Option<String>> {
+    /// `total_realized_pnl` is the realized P&L summed over every asset,
+    /// converted to the base currency at `prices`.
+    pub fn total_realized_pnl(&self, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        self.realized_pnl.iter()
+            .map(|(asset, pnl)| self.to_base(*pnl, &self.instruments.currency(asset), prices))
+            .sum()
+    }
+
+    /// `unrealized_pnl` is the open P&L of the position in `asset` marked at
+    /// `price`, or zero if there is no position.
+    pub fn unrealized_pnl(&self, asset: &str, price: Decimal) -> Decimal {
+        match self.portfolio.get(asset) {
+            Some(pos) => self.instruments.notional(asset, pos.lots, price - pos.cost_basis),
+            None => Decimal::new(0, 0),
+        }
+    }
+
+    /// `total_unrealized_pnl` is the open P&L of every position, marked at
+    /// `prices` and converted to the base currency. Positions without a
+    /// price are marked at their cost basis.
+    pub fn total_unrealized_pnl(&self, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        self.portfolio.values()
+            .map(|pos| {
+                let pnl = self.unrealized_pnl(&pos.asset, Self::mark(pos, prices));
+                self.to_base(pnl, &self.instruments.currency(&pos.asset), prices)
+            })
+            .sum()
+    }
+
+    /// `balance` is the cash held in `currency`.
+    pub fn balance(&self, currency: &str) -> Decimal {
+        if currency == self.base_currency {
+            self.cash
+        } else {
+            self.balances.get(currency).copied().unwrap_or_default()
+        }
+    }
+
+    /// `credit` adds `amount` to the cash balance in `asset`'s currency.
+    fn credit(&mut self, asset: &str, amount: Decimal) {
+        let currency = self.instruments.currency(asset);
+        self.deposit(&currency, amount);
+    }
+
+    /// `deposit` adds `amount` to the cash balance in `currency`.
+    fn deposit(&mut self, currency: &str, amount: Decimal) {
+        if currency == self.base_currency {
+            self.cash += amount;
+        } else {
+            *self.balances.entry(currency.to_string()).or_default() += amount;
+        }
+    }
+
+    /// `to_base` converts `amount` of `currency` to the base currency at
+    /// the FX rates in `prices`, failing if there is no rate.
+    pub fn to_base(&self, amount: Decimal, currency: &str, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        fx::convert(amount, currency, &self.base_currency, prices)
+            .ok_or_else(|| anyhow!("No FX rate to convert {} to {}", currency, self.base_currency))
+    }
+
+    /// `base_notional` is the value of `lots` of `asset` at `price`, in the
+    /// base currency.
+    fn base_notional(&self, asset: &str, lots: isize, price: Decimal, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        let notional = self.instruments.notional(asset, lots, price);
+        self.to_base(notional, &self.instruments.currency(asset), prices)
+    }
+
+    fn mark(pos: &Position, prices: &HashMap<String, Decimal>) -> Decimal {
+        prices.get(&pos.asset).copied().unwrap_or(pos.cost_basis)
+    }
+
+    /// `equity` is cash in every currency, plus any short collateral, plus
+    /// every position marked at `prices`, all in the base currency.
+    /// Positions without a price are marked at their cost basis.
+    pub fn equity(&self, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        let positions: Decimal = self.portfolio.values()
+            .map(|pos| self.base_notional(&pos.asset, pos.lots, Self::mark(pos, prices), prices))
+            .sum::<anyhow::Result<_>>()?;
+        let collateral: Decimal = self.short_collateral.iter()
+            .map(|(asset, c)| self.to_base(*c, &self.instruments.currency(asset), prices))
+            .sum::<anyhow::Result<_>>()?;
+        let balances: Decimal = self.balances.iter()
+            .map(|(currency, b)| self.to_base(*b, currency, prices))
+            .sum::<anyhow::Result<_>>()?;
+        Ok(self.cash + balances + collateral + positions)
+    }
+
+    /// `margin_requirement` is the initial margin (or `maintenance` margin)
+    /// needed for every position, marked at `prices`, in the base currency.
+    pub fn margin_requirement(&self, prices: &HashMap<String, Decimal>, maintenance: bool) -> anyhow::Result<Decimal> {
+        self.portfolio.values()
+            .map(|pos| {
+                let req = self.margin.requirement(&pos.asset);
+                let rate = if maintenance { req.maintenance } else { req.initial };
+                Ok(rate * self.base_notional(&pos.asset, pos.lots, Self::mark(pos, prices), prices)?.abs())
+            })
+            .sum()
+    }
+
+    /// `excess_equity` is equity above the initial margin requirement.
+    pub fn excess_equity(&self, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        Ok(self.equity(prices)? - self.margin_requirement(prices, false)?)
+    }
+
+    /// `buying_power` is the notional value of new positions `excess_equity`
+    /// could support at the default initial margin rate.
+    pub fn buying_power(&self, prices: &HashMap<String, Decimal>) -> anyhow::Result<Decimal> {
+        let excess = self.excess_equity(prices)?.max(Decimal::new(0, 0));
+        let rate = self.margin.default.initial;
+        if rate.is_zero() {
+            Ok(excess)
+        } else {
+            Ok(excess / rate)
+        }
+    }
+
+    /// `check_margin` fails if trading `p` and paying `fee` would leave the
+    /// account's equity below its initial margin requirement. Trades that do
+    /// not increase the requirement, such as closing out, always pass.
+    fn check_margin(&self, p: &Position, fee: Decimal, prices: &HashMap<String, Decimal>) -> anyhow::Result<()> {
+        let fee = self.to_base(fee, &self.instruments.currency(&p.asset), prices)?;
+        let mut prices = prices.clone();
+        prices.insert(p.asset.clone(), p.cost_basis);
+        let rate = self.margin.requirement(&p.asset).initial;
+        let held = self.portfolio.get(&p.asset).map(|pos| pos.lots).unwrap_or(0);
+        let before = self.margin_requirement(&prices, false)?;
+        let after = before + rate * self.base_notional(&p.asset, (held + p.lots).abs() - held.abs(), p.cost_basis.abs(), &prices)?;
+        let equity = self.equity(&prices)? - fee;
+        if after > before && equity < after {
+            return Err(anyhow!("Order would exceed buying power: {} lots of {} needs {} initial margin, equity is {}", p.lots, p.asset, after, equity));
+        }
+        Ok(())
+    }
+
+    /// `position` applies a trade of `p.lots` at `p.cost_basis` to the
+    /// portfolio, if the account has the margin for it with other positions
+    /// marked at `prices`. A trade that takes a position through zero is
+    /// split into a close of the existing side, realizing its P&L, and a
+    /// fresh position on the other side with the trade price as its cost
+    /// basis.
+    pub fn position(self: &mut Self, p: Position, prices: &HashMap<String, Decimal>) -> anyhow::Result<()> {
         // Check if account can support position
-        let cost = p.cost_basis.checked_mul(Decimal::new(p.lots as i64, 0)).unwrap();
-        if cost.gt(&self.cash) {
-            return Err(anyhow!("Not enough cash in account to open position"));
+        self.check_margin(&p, Decimal::new(0, 0), prices)?;
+        self.book(p);
+        Ok(())
+    }
+
+    fn book(&mut self, p: Position) {
+        let held = self.portfolio.get(&p.asset).map(|pos| pos.lots).unwrap_or(0);
+        if held != 0 && held.signum() != p.lots.signum() && p.lots.abs() > held.abs() {
+            let close = Position{asset: p.asset.clone(), lots: -held, cost_basis: p.cost_basis};
+            let open = Position{asset: p.asset.clone(), lots: p.lots + held, cost_basis: p.cost_basis};
+            self.book(close);
+            return self.book(open);
+        }
+
+        let cost = self.instruments.notional(&p.asset, p.lots, p.cost_basis);
+        let (asset, lots) = (p.asset.clone(), p.lots);
+        let response = self._position(p, cost);
+        self.hold_short_proceeds(&asset, held, lots, cost);
+        if let Some(asset) = response {
+            self.portfolio.remove(&asset);
         }
+    }
 
+    /// `hold_short_proceeds` moves short sale proceeds between cash and
+    /// `short_collateral` when they are held as collateral. `held` is the
+    /// position before a trade of `lots` costing `cost`, which never crosses zero.
+    fn hold_short_proceeds(&mut self, asset: &str, held: isize, lots: isize, cost: Decimal) {
+        if self.margin.short_proceeds != ShortProceeds::Collateral {
+            return;
+        }
+        if lots < 0 && held <= 0 {
+            // opening or adding to a short
+            *self.short_collateral.entry(asset.to_string()).or_default() -= cost;
+            self.credit(asset, cost);
+        } else if lots > 0 && held < 0 {
+            // covering releases collateral in proportion to the lots bought back
+            let collateral = self.short_collateral.remove(asset).unwrap_or_default();
+            let release = collateral * Decimal::new(lots as i64, 0) / Decimal::new(-held as i64, 0);
+            self.credit(asset, release);
+            if release != collateral {
+                self.short_collateral.insert(asset.to_string(), collateral - release);
+            }
+        }
+    }
+
+    /// `_position` opens, adds to or reduces a position without crossing
+    /// zero, returning the asset if it is now flat.
+    fn _position(self: &mut Self, p: Position, cost: Decimal) -> Option<String> {
+        self.credit(&p.asset, -cost);
         let maybe_pos = self.portfolio.get_mut(&p.asset);
 
         match maybe_pos {
             Some(pos) => {
-                let current_equity = pos.cost_basis.checked_mul(Decimal::new(pos.lots as i64, 0)).unwrap();
-                let new_equity = cost;
-                let total_equity = current_equity.checked_add(new_equity).unwrap();
-                pos.lots += p.lots;
-                if pos.lots != 0 {
-                    let new_cb = total_equity.checked_div(Decimal::new(pos.lots as i64, 0)).unwrap();
-                    pos.cost_basis = new_cb;
+                if pos.lots.signum() != p.lots.signum() {
+                    // reducing the position keeps its cost basis and realizes P&L on the lots closed
+                    let realized = self.instruments.notional(&p.asset, p.lots, pos.cost_basis - p.cost_basis);
+                    *self.realized_pnl.entry(p.asset.clone()).or_default() += realized;
+                    pos.lots += p.lots;
+                } else {
+                    let current_equity = pos.cost_basis.checked_mul(Decimal::new(pos.lots as i64, 0)).unwrap();
+                    let new_equity = p.cost_basis.checked_mul(Decimal::new(p.lots as i64, 0)).unwrap();
+                    let total_equity = current_equity.checked_add(new_equity).unwrap();
+                    pos.lots += p.lots;
+                    pos.cost_basis = total_equity.checked_div(Decimal::new(pos.lots as i64, 0)).unwrap();
                 }
-                self.cash = self.cash.checked_sub(cost).unwrap();
                 self.trades.push(p);
                 if pos.lots == 0 {
-                    Ok(Some(pos.asset.clone()))
+                    Some(pos.asset.clone())
                 } else {
-                    Ok(None)
+                    None
                 }
             },
             None => {
-                self.cash = self.cash.checked_sub(cost).unwrap();
                 self.portfolio.insert(p.asset.clone(), p.clone());
                 self.trades.push(p);
-                Ok(None)
+                None
             }
         }
     }
-}
\ No newline at end of file
+}
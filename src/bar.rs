@@ -0,0 +1,61 @@
+use chrono::prelude::*;
+use rust_decimal::prelude::*;
+
+use crate::Tick;
+
+/// `Bar` holds the open, high, low and close prices of an asset over an
+/// interval ending at `timestamp`, and the `volume` traded in it.
+/// ```
+/// use rsbacktester::bar::Bar;
+/// use rust_decimal::Decimal;
+/// use chrono::Utc;
+///
+/// let b = Bar{timestamp: Utc::now(), asset: "AAPL".to_string(), open: Decimal::new(300, 0), high: Decimal::new(305, 0), low: Decimal::new(298, 0), close: Decimal::new(301, 0), volume: Decimal::new(1000, 0)};
+/// assert!(b.to_tick().bid == b.close);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bar {
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub open: Decimal,
+    pub high: Decimal,
+    pub low: Decimal,
+    pub close: Decimal,
+    pub volume: Decimal,
+}
+
+impl Bar {
+    /// `to_tick` is the bar's close as a `Tick` with no spread, which is
+    /// what the engine marks positions and equity at.
+    pub fn to_tick(&self) -> Tick {
+        Tick {
+            timestamp: self.timestamp,
+            asset: self.asset.clone(),
+            bid: self.close,
+            ask: self.close,
+        }
+    }
+
+    /// `field` is the value of an indicator input: "open", "high", "low",
+    /// "close" or "volume".
+    pub fn field(&self, name: &str) -> Option<Decimal> {
+        match name {
+            "open" => Some(self.open),
+            "high" => Some(self.high),
+            "low" => Some(self.low),
+            "close" => Some(self.close),
+            "volume" => Some(self.volume),
+            _ => None,
+        }
+    }
+}
+
+/// `BarFill` decides the price market orders fill at in a bar: its `Open`
+/// or its `Close`. Limits and stops fill when the bar's range reaches their
+/// price, at that price or at the open if the bar gaps through it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum BarFill {
+    #[default]
+    Open,
+    Close,
+}
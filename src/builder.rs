@@ -0,0 +1,549 @@
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::account::Account;
+use crate::bar::Bar;
+use crate::commission::{CommissionModel, Commissions, NoCommission, PerLot, PerTrade, Percentage, Tier, Tiered};
+use crate::indicators::{self, Indicator};
+use crate::instrument::{Instrument, Instruments};
+use crate::loader::{self, CsvSchema, LoadError};
+use crate::slippage::{FixedTicks, NoSlippage, SlippageModel, SpreadFraction, SquareRootImpact, VolatilityScaled};
+use crate::validation::Validation;
+use crate::{Engine, ExecutionTiming, Mode, TS};
+
+/// `DataSource` is where an engine's market data comes from: a tick file
+/// read with `schema` and checked with `validation`, many tick files (a
+/// directory or glob `pattern`) merged in time order, a bar file, or ticks
+/// or bars already in memory.
+#[derive(Debug, Clone)]
+pub enum DataSource {
+    Ticks { path: PathBuf, schema: Box<CsvSchema>, validation: Validation },
+    TickFiles { pattern: PathBuf, schema: Box<CsvSchema>, validation: Validation },
+    Bars { path: PathBuf },
+    TickSeries(TS),
+    BarSeries(Vec<Bar>),
+}
+
+/// `BuildError` is why `EngineBuilder::build` could not make an engine:
+/// the data could not be loaded, or the configuration does not make sense.
+#[derive(Debug)]
+pub enum BuildError {
+    Load(LoadError),
+    Config(String),
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BuildError::Load(e) => write!(f, "{}", e),
+            BuildError::Config(message) => write!(f, "Invalid engine configuration: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuildError::Load(e) => Some(e),
+            BuildError::Config(_) => None,
+        }
+    }
+}
+
+impl From<LoadError> for BuildError {
+    fn from(e: LoadError) -> Self {
+        BuildError::Load(e)
+    }
+}
+
+/// `EngineBuilder` puts together an `Engine` from a data source, starting
+/// cash and the base currency it is held and reported in, mode, execution timing, commission and slippage models,
+/// instruments and indicators, checking they fit together before loading
+/// any data.
+/// ```
+/// use rsbacktester::builder::EngineBuilder;
+/// use rsbacktester::commission::PerLot;
+/// use rust_decimal::Decimal;
+///
+/// let engine = EngineBuilder::new()
+///     .ticks("test_resources/ticks.csv")
+///     .cash(Decimal::new(10000, 0))
+///     .commission_model(PerLot(Decimal::new(1, 0)))
+///     .build()
+///     .unwrap();
+/// assert!(engine.prices.ticks.len() == 30);
+/// ```
+#[derive(Debug, Clone)]
+pub struct EngineBuilder {
+    pub data: Option<DataSource>,
+    pub cash: Decimal,
+    pub base_currency: String,
+    pub mode: Mode,
+    pub execution: ExecutionTiming,
+    pub max_fill_lots: Option<usize>,
+    pub commissions: Commissions,
+    pub slippage: Arc<dyn SlippageModel>,
+    pub instruments: Vec<Instrument>,
+    pub instrument_files: Vec<PathBuf>,
+    pub indicators: Vec<(String, Indicator)>,
+}
+
+impl Default for EngineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EngineBuilder {
+    pub fn new() -> Self {
+        Self {
+            data: None,
+            cash: Decimal::new(0, 0),
+            base_currency: "USD".to_string(),
+            mode: Mode::Backtest,
+            execution: ExecutionTiming::default(),
+            max_fill_lots: None,
+            commissions: Commissions::default(),
+            slippage: Arc::new(NoSlippage),
+            instruments: vec![],
+            instrument_files: vec![],
+            indicators: vec![],
+        }
+    }
+
+    pub fn data(mut self, source: DataSource) -> Self {
+        self.data = Some(source);
+        self
+    }
+
+    /// `ticks` reads tick data from `path` in the default layout, with the
+    /// default `Validation`, which only reports issues.
+    pub fn ticks<P: AsRef<Path>>(self, path: P) -> Self {
+        self.data(DataSource::Ticks {
+            path: path.as_ref().to_path_buf(),
+            schema: Box::default(),
+            validation: Validation::default(),
+        })
+    }
+
+    /// `tick_files` reads ticks from every file in a directory, or matching
+    /// a glob, in the default layout.
+    pub fn tick_files<P: AsRef<Path>>(self, pattern: P) -> Self {
+        self.data(DataSource::TickFiles {
+            pattern: pattern.as_ref().to_path_buf(),
+            schema: Box::default(),
+            validation: Validation::default(),
+        })
+    }
+
+    pub fn bars<P: AsRef<Path>>(self, path: P) -> Self {
+        self.data(DataSource::Bars { path: path.as_ref().to_path_buf() })
+    }
+
+    pub fn cash(mut self, cash: Decimal) -> Self {
+        self.cash = cash;
+        self
+    }
+
+    pub fn base_currency(mut self, currency: &str) -> Self {
+        self.base_currency = currency.to_string();
+        self
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn execution(mut self, execution: ExecutionTiming) -> Self {
+        self.execution = execution;
+        self
+    }
+
+    pub fn max_fill_lots(mut self, lots: usize) -> Self {
+        self.max_fill_lots = Some(lots);
+        self
+    }
+
+    pub fn commission_model<C: CommissionModel + 'static>(mut self, model: C) -> Self {
+        self.commissions.default = Arc::new(model);
+        self
+    }
+
+    pub fn asset_commission_model<C: CommissionModel + 'static>(mut self, asset: String, model: C) -> Self {
+        self.commissions.per_asset.insert(asset, Arc::new(model));
+        self
+    }
+
+    pub fn slippage_model<S: SlippageModel + 'static>(mut self, model: S) -> Self {
+        self.slippage = Arc::new(model);
+        self
+    }
+
+    pub fn instrument(mut self, instrument: Instrument) -> Self {
+        self.instruments.push(instrument);
+        self
+    }
+
+    /// `instrument_file` registers the instruments in a `.toml` or `.csv`
+    /// file when the engine is built.
+    pub fn instrument_file<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.instrument_files.push(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn indicator(mut self, name: String, indicator: Indicator) -> Self {
+        self.indicators.push((name, indicator));
+        self
+    }
+
+    /// `check` fails if the settings cannot make a working engine: there is
+    /// no data, cash is negative, there is no base currency, the mode is not backtesting, fills are
+    /// capped at zero lots, or indicators clash or read an input that does
+    /// not exist.
+    pub fn check(&self) -> Result<(), BuildError> {
+        let invalid = |message: String| Err(BuildError::Config(message));
+        match &self.data {
+            None => return invalid("no data source".to_string()),
+            Some(DataSource::Ticks { path, .. }) | Some(DataSource::TickFiles { pattern: path, .. }) | Some(DataSource::Bars { path })
+                if path.as_os_str().is_empty() =>
+            {
+                return invalid("no data file".to_string());
+            }
+            _ => {}
+        }
+        if self.cash.is_sign_negative() {
+            return invalid(format!("starting cash {} is negative", self.cash));
+        }
+        if self.base_currency.is_empty() {
+            return invalid("no base currency".to_string());
+        }
+        if self.mode != Mode::Backtest {
+            return invalid(format!("{:?} mode is not supported, only backtesting", self.mode));
+        }
+        if self.max_fill_lots == Some(0) {
+            return invalid("max_fill_lots must be at least 1".to_string());
+        }
+        let inputs = ["price", "open", "high", "low", "close", "volume"];
+        for (i, (name, indicator)) in self.indicators.iter().enumerate() {
+            if self.indicators[..i].iter().any(|(n, _)| n == name) {
+                return invalid(format!("indicator {:?} is registered twice", name));
+            }
+            let input = indicator.get_input();
+            if !inputs.contains(&input.as_str()) && !self.indicators.iter().any(|(n, _)| *n == input) {
+                return invalid(format!("indicator {:?} reads {:?}, which is not an input or another indicator", name, input));
+            }
+        }
+        Ok(())
+    }
+
+    /// `build` checks the settings, loads the data and returns the engine.
+    pub fn build(self) -> Result<Engine, BuildError> {
+        self.check()?;
+        let mut instruments = Instruments::default();
+        for path in &self.instrument_files {
+            let loaded = Instruments::load(path).map_err(|e| BuildError::Config(format!("could not load instruments: {}", e)))?;
+            instruments.extend(loaded);
+        }
+        for instrument in self.instruments {
+            instruments.register(instrument);
+        }
+
+        let (mut engine, report) = match self.data.expect("checked above") {
+            DataSource::Ticks { path, schema, validation } => {
+                let (ts, report) = loader::load_ticks(&path, &schema, &validation)?;
+                (crate::new_engine(ts, vec![], 0), Some(report))
+            }
+            DataSource::TickFiles { pattern, schema, validation } => {
+                let (ts, report) = loader::load_tick_files(&pattern, &schema, &validation)?;
+                (crate::new_engine(ts, vec![], 0), Some(report))
+            }
+            DataSource::Bars { path } => {
+                let bars = loader::load_bars(&path)?;
+                (crate::bar_engine(bars, 0), None)
+            }
+            DataSource::TickSeries(ts) if ts.ticks.is_empty() => return Err(LoadError::Empty.into()),
+            DataSource::TickSeries(ts) => (crate::new_engine(ts, vec![], 0), None),
+            DataSource::BarSeries(bars) if bars.is_empty() => return Err(LoadError::Empty.into()),
+            DataSource::BarSeries(bars) => (crate::bar_engine(bars, 0), None),
+        };
+        engine.acct = Account::with_base_currency(self.cash, &self.base_currency);
+        engine.acct.instruments = instruments;
+        engine.mode = self.mode;
+        engine.execution = self.execution;
+        engine.max_fill_lots = self.max_fill_lots;
+        engine.commissions = self.commissions;
+        engine.slippage = self.slippage;
+        engine.data_quality = report;
+        for (name, indicator) in self.indicators {
+            engine.register_indicator(name, indicator);
+        }
+        Ok(engine)
+    }
+
+    /// `from_config` sets up a builder from an `EngineConfig`.
+    pub fn from_config(config: &EngineConfig) -> Self {
+        let mut builder = Self::new()
+            .data(config.data.source())
+            .cash(config.cash)
+            .base_currency(&config.base_currency)
+            .mode(config.mode.clone())
+            .execution(config.execution.timing());
+        builder.max_fill_lots = config.max_fill_lots;
+        builder.commissions.default = config.commission.model();
+        for (asset, commission) in &config.asset_commissions {
+            builder.commissions.per_asset.insert(asset.clone(), commission.model());
+        }
+        builder.slippage = config.slippage.model();
+        builder.instruments = config.instruments.clone();
+        builder.instrument_files = config.instrument_files.clone();
+        builder.indicators = config.indicators.iter().map(|i| (i.name.clone(), i.indicator())).collect();
+        builder
+    }
+}
+
+/// `DataKind` says whether a data file holds ticks or bars, or whether the
+/// data path is a directory or glob of tick files (`tick_files`).
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataKind {
+    #[default]
+    Ticks,
+    TickFiles,
+    Bars,
+}
+
+/// `DataConfig` is the `[data]` table of an `EngineConfig`. It can be left
+/// out, or leave out `path`, when the data file is given some other way,
+/// e.g. on the command line.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataConfig {
+    #[serde(default)]
+    pub path: PathBuf,
+    #[serde(default)]
+    pub kind: DataKind,
+    #[serde(default)]
+    pub schema: CsvSchema,
+    #[serde(default)]
+    pub validation: Validation,
+}
+
+impl DataConfig {
+    pub fn source(&self) -> DataSource {
+        match self.kind {
+            DataKind::Ticks => DataSource::Ticks {
+                path: self.path.clone(),
+                schema: Box::new(self.schema.clone()),
+                validation: self.validation.clone(),
+            },
+            DataKind::TickFiles => DataSource::TickFiles {
+                pattern: self.path.clone(),
+                schema: Box::new(self.schema.clone()),
+                validation: self.validation.clone(),
+            },
+            DataKind::Bars => DataSource::Bars { path: self.path.clone() },
+        }
+    }
+}
+
+/// `ExecutionConfig` is `ExecutionTiming` as written in a config file:
+/// "same_tick", "next_tick" or `{ latency = { millis = 50 } }`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExecutionConfig {
+    SameTick,
+    #[default]
+    NextTick,
+    Latency { millis: i64 },
+}
+
+impl ExecutionConfig {
+    pub fn timing(&self) -> ExecutionTiming {
+        match self {
+            ExecutionConfig::SameTick => ExecutionTiming::SameTick,
+            ExecutionConfig::NextTick => ExecutionTiming::NextTick,
+            ExecutionConfig::Latency { millis } => ExecutionTiming::Latency(chrono::Duration::milliseconds(*millis)),
+        }
+    }
+}
+
+/// `CommissionConfig` picks a commission model by name, e.g.
+/// `{ model = "per_lot", amount = "1.5" }`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum CommissionConfig {
+    #[default]
+    None,
+    PerLot { amount: Decimal },
+    PerTrade { amount: Decimal },
+    Percentage { rate: Decimal },
+    Tiered {
+        tiers: Vec<Tier>,
+        #[serde(default)]
+        minimum: Decimal,
+    },
+}
+
+impl CommissionConfig {
+    pub fn model(&self) -> Arc<dyn CommissionModel> {
+        match self {
+            CommissionConfig::None => Arc::new(NoCommission),
+            CommissionConfig::PerLot { amount } => Arc::new(PerLot(*amount)),
+            CommissionConfig::PerTrade { amount } => Arc::new(PerTrade(*amount)),
+            CommissionConfig::Percentage { rate } => Arc::new(Percentage(*rate)),
+            CommissionConfig::Tiered { tiers, minimum } => Arc::new(Tiered { tiers: tiers.clone(), minimum: *minimum }),
+        }
+    }
+}
+
+/// `SlippageConfig` picks a slippage model by name, e.g.
+/// `{ model = "spread_fraction", fraction = "0.5" }`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum SlippageConfig {
+    #[default]
+    None,
+    FixedTicks { ticks: u32, tick_size: Decimal },
+    SpreadFraction { fraction: Decimal },
+    VolatilityScaled { indicator: String, multiplier: Decimal },
+    SquareRootImpact { coefficient: f64, volatility: f64, volume: f64 },
+}
+
+impl SlippageConfig {
+    pub fn model(&self) -> Arc<dyn SlippageModel> {
+        match self {
+            SlippageConfig::None => Arc::new(NoSlippage),
+            SlippageConfig::FixedTicks { ticks, tick_size } => Arc::new(FixedTicks { ticks: *ticks, tick_size: *tick_size }),
+            SlippageConfig::SpreadFraction { fraction } => Arc::new(SpreadFraction(*fraction)),
+            SlippageConfig::VolatilityScaled { indicator, multiplier } => Arc::new(VolatilityScaled { indicator: indicator.clone(), multiplier: *multiplier }),
+            SlippageConfig::SquareRootImpact { coefficient, volatility, volume } => Arc::new(SquareRootImpact {
+                coefficient: *coefficient,
+                volatility: *volatility,
+                volume: *volume,
+            }),
+        }
+    }
+}
+
+/// `IndicatorKind` names the kinds of `Indicator`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IndicatorKind {
+    MovingAverage,
+    Momentum,
+    StandardDeviation,
+}
+
+/// `IndicatorConfig` is one `[[indicator]]` table of an `EngineConfig`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct IndicatorConfig {
+    pub name: String,
+    pub kind: IndicatorKind,
+    pub length: usize,
+    #[serde(default = "default_input")]
+    pub input: String,
+}
+
+fn default_input() -> String {
+    "price".to_string()
+}
+
+impl IndicatorConfig {
+    pub fn indicator(&self) -> Indicator {
+        let input = self.input.clone();
+        match self.kind {
+            IndicatorKind::MovingAverage => Indicator::MovingAverage(indicators::MovingAverage::new(self.length, input)),
+            IndicatorKind::Momentum => Indicator::Momentum(indicators::Momentum::new(self.length, input)),
+            IndicatorKind::StandardDeviation => Indicator::StandardDeviation(indicators::StandardDeviation::new(self.length, input)),
+        }
+    }
+}
+
+fn default_mode() -> Mode {
+    Mode::Backtest
+}
+
+fn default_base_currency() -> String {
+    "USD".to_string()
+}
+
+/// `EngineConfig` is everything `EngineBuilder` needs, as read from a TOML
+/// file so a run can be reproduced from a checked-in config, e.g.
+/// ```toml
+/// cash = 10000
+/// base_currency = "USD"
+/// execution = "next_tick"
+/// commission = { model = "per_lot", amount = "1" }
+/// slippage = { model = "spread_fraction", fraction = "0.5" }
+///
+/// [data]
+/// path = "ticks.csv"
+/// validation = { policy = "sort" }
+///
+/// [[indicator]]
+/// name = "sma"
+/// kind = "moving_average"
+/// length = 20
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EngineConfig {
+    #[serde(default)]
+    pub data: DataConfig,
+    pub cash: Decimal,
+    #[serde(default = "default_base_currency")]
+    pub base_currency: String,
+    #[serde(default = "default_mode")]
+    pub mode: Mode,
+    #[serde(default)]
+    pub execution: ExecutionConfig,
+    #[serde(default)]
+    pub max_fill_lots: Option<usize>,
+    #[serde(default)]
+    pub commission: CommissionConfig,
+    #[serde(default)]
+    pub asset_commissions: BTreeMap<String, CommissionConfig>,
+    #[serde(default)]
+    pub slippage: SlippageConfig,
+    #[serde(default, rename = "instrument")]
+    pub instruments: Vec<Instrument>,
+    #[serde(default)]
+    pub instrument_files: Vec<PathBuf>,
+    #[serde(default, rename = "indicator")]
+    pub indicators: Vec<IndicatorConfig>,
+}
+
+impl EngineConfig {
+    pub fn from_toml(contents: &str) -> Result<Self, BuildError> {
+        toml::from_str(contents).map_err(|e| BuildError::Config(e.to_string()))
+    }
+
+    /// `load` reads a config file. Relative data and instrument paths in it
+    /// are taken to be relative to the file.
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, BuildError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| BuildError::Load(LoadError::Io(e)))?;
+        let mut config = Self::from_toml(&contents)?;
+        config.relative_to(path.parent().unwrap_or_else(|| Path::new("")));
+        Ok(config)
+    }
+
+    /// `relative_to` joins relative data and instrument paths onto `dir`.
+    pub fn relative_to(&mut self, dir: &Path) {
+        if !self.data.path.as_os_str().is_empty() {
+            self.data.path = dir.join(&self.data.path);
+        }
+        for file in self.instrument_files.iter_mut() {
+            *file = dir.join(&*file);
+        }
+    }
+
+    pub fn build(&self) -> Result<Engine, BuildError> {
+        EngineBuilder::from_config(self).build()
+    }
+}
@@ -0,0 +1,113 @@
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+/// `CommissionModel` works out the fee charged for filling `lots` at `price`,
+/// where each lot is `multiplier` units of the asset, so the fill's notional
+/// value is `lots * price * multiplier`. `lots` is signed, so models should
+/// use its absolute value.
+pub trait CommissionModel: Debug + Send + Sync {
+    fn commission(&self, lots: isize, price: Decimal, multiplier: Decimal) -> Decimal;
+}
+
+/// `NoCommission` charges nothing, and is what an `Engine` starts with.
+#[derive(Debug, Clone)]
+pub struct NoCommission;
+
+impl CommissionModel for NoCommission {
+    fn commission(&self, _lots: isize, _price: Decimal, _multiplier: Decimal) -> Decimal {
+        Decimal::new(0, 0)
+    }
+}
+
+/// `PerLot` charges a fixed amount for every lot filled.
+#[derive(Debug, Clone)]
+pub struct PerLot(pub Decimal);
+
+impl CommissionModel for PerLot {
+    fn commission(&self, lots: isize, _price: Decimal, _multiplier: Decimal) -> Decimal {
+        self.0 * Decimal::new(lots.abs() as i64, 0)
+    }
+}
+
+/// `PerTrade` charges a fixed amount for every fill, whatever its size.
+#[derive(Debug, Clone)]
+pub struct PerTrade(pub Decimal);
+
+impl CommissionModel for PerTrade {
+    fn commission(&self, _lots: isize, _price: Decimal, _multiplier: Decimal) -> Decimal {
+        self.0
+    }
+}
+
+/// `Percentage` charges a fraction of the notional value of the fill, so
+/// `Percentage(Decimal::new(1, 3))` is 0.1%.
+#[derive(Debug, Clone)]
+pub struct Percentage(pub Decimal);
+
+impl CommissionModel for Percentage {
+    fn commission(&self, lots: isize, price: Decimal, multiplier: Decimal) -> Decimal {
+        self.0 * price.abs() * Decimal::new(lots.abs() as i64, 0) * multiplier
+    }
+}
+
+/// `Tier` is one step of a `Tiered` schedule: fills of at least `min_lots`
+/// are charged `per_lot`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Tier {
+    pub min_lots: usize,
+    pub per_lot: Decimal,
+}
+
+/// `Tiered` charges a per-lot rate that depends on the size of the fill,
+/// using the tier with the largest `min_lots` the fill reaches, and never
+/// less than `minimum`.
+#[derive(Debug, Clone)]
+pub struct Tiered {
+    pub tiers: Vec<Tier>,
+    pub minimum: Decimal,
+}
+
+impl CommissionModel for Tiered {
+    fn commission(&self, lots: isize, _price: Decimal, _multiplier: Decimal) -> Decimal {
+        let size = lots.unsigned_abs();
+        let rate = self.tiers.iter()
+            .filter(|t| t.min_lots <= size)
+            .max_by_key(|t| t.min_lots)
+            .map(|t| t.per_lot)
+            .unwrap_or_default();
+        (rate * Decimal::new(size as i64, 0)).max(self.minimum)
+    }
+}
+
+/// `Commissions` holds the `CommissionModel` used for each asset: a
+/// `default` and any per-asset overrides.
+#[derive(Debug, Clone)]
+pub struct Commissions {
+    pub default: Arc<dyn CommissionModel>,
+    pub per_asset: HashMap<String, Arc<dyn CommissionModel>>,
+}
+
+impl Default for Commissions {
+    fn default() -> Self {
+        Self {
+            default: Arc::new(NoCommission),
+            per_asset: HashMap::new(),
+        }
+    }
+}
+
+impl Commissions {
+    pub fn for_asset(&self, asset: &str) -> &dyn CommissionModel {
+        match self.per_asset.get(asset) {
+            Some(model) => model.as_ref(),
+            None => self.default.as_ref(),
+        }
+    }
+
+    pub fn commission(&self, asset: &str, lots: isize, price: Decimal, multiplier: Decimal) -> Decimal {
+        self.for_asset(asset).commission(lots, price, multiplier)
+    }
+}
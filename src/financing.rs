@@ -0,0 +1,55 @@
+use chrono::prelude::*;
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+
+/// `Financing` holds the annualised rates used to accrue interest and
+/// borrow fees: `credit_rate` is paid on positive cash, `debit_rate` is
+/// charged on negative cash, and `borrow_rates` (or `default_borrow_rate`)
+/// are charged on the market value of short positions. Rates are fractions,
+/// so 0.05 is 5% a year, and accrue over `days_per_year` days.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Financing {
+    pub credit_rate: Decimal,
+    pub debit_rate: Decimal,
+    pub default_borrow_rate: Decimal,
+    pub borrow_rates: HashMap<String, Decimal>,
+    pub days_per_year: Decimal,
+}
+
+impl Default for Financing {
+    fn default() -> Self {
+        Self {
+            credit_rate: Decimal::new(0, 0),
+            debit_rate: Decimal::new(0, 0),
+            default_borrow_rate: Decimal::new(0, 0),
+            borrow_rates: HashMap::new(),
+            days_per_year: Decimal::new(365, 0),
+        }
+    }
+}
+
+impl Financing {
+    pub fn borrow_rate(&self, asset: &str) -> Decimal {
+        self.borrow_rates.get(asset).copied().unwrap_or(self.default_borrow_rate)
+    }
+}
+
+/// `AccrualKind` says what an `Accrual` was for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AccrualKind {
+    CreditInterest,
+    DebitInterest,
+    BorrowFee(String),
+}
+
+/// `Accrual` is a ledger entry for interest or a borrow fee posted to the
+/// account at `timestamp` for the previous `days`. `amount` is in the
+/// account's base currency and signed: positive amounts were paid to the
+/// account.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accrual {
+    pub timestamp: DateTime<Utc>,
+    pub kind: AccrualKind,
+    pub days: i64,
+    pub amount: Decimal,
+}
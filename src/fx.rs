@@ -0,0 +1,37 @@
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+
+/// `rate` is the price of one unit of `from` in `to`, taken from the mid
+/// prices of FX pairs in `prices`. A pair is found as `EURUSD` or `EUR/USD`,
+/// either way round, and failing that is crossed through USD.
+pub fn rate(from: &str, to: &str, prices: &HashMap<String, Decimal>) -> Option<Decimal> {
+    if from == to {
+        return Some(Decimal::new(1, 0));
+    }
+    direct(from, to, prices).or_else(|| {
+        if from == "USD" || to == "USD" {
+            return None;
+        }
+        Some(direct(from, "USD", prices)? * direct("USD", to, prices)?)
+    })
+}
+
+/// `convert` is `amount` of `from` in `to`, if there is a `rate` for them.
+pub fn convert(amount: Decimal, from: &str, to: &str, prices: &HashMap<String, Decimal>) -> Option<Decimal> {
+    rate(from, to, prices).map(|r| amount * r)
+}
+
+fn direct(from: &str, to: &str, prices: &HashMap<String, Decimal>) -> Option<Decimal> {
+    let quote = |base: &str, quote: &str| {
+        prices.get(&format!("{}{}", base, quote))
+            .or_else(|| prices.get(&format!("{}/{}", base, quote)))
+            .copied()
+    };
+    if let Some(price) = quote(from, to) {
+        return Some(price);
+    }
+    match quote(to, from) {
+        Some(price) if !price.is_zero() => Some(Decimal::new(1, 0) / price),
+        _ => None,
+    }
+}
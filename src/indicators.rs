@@ -1,3 +1,4 @@
+#![allow(clippy::needless_borrow, clippy::single_match)]
 use rust_decimal::prelude::*;
 use std::collections::VecDeque;
 use std::fmt::Debug;
@@ -6,6 +7,7 @@ use std::fmt::Debug;
 pub enum Indicator {
     MovingAverage(MovingAverage),
     Momentum(Momentum),
+    StandardDeviation(StandardDeviation),
 }
 
 impl Indicator {
@@ -13,24 +15,28 @@ impl Indicator {
         match self {
             Indicator::MovingAverage(i) => i.get_input(),
             Indicator::Momentum(i) => i.get_input(),
+            Indicator::StandardDeviation(i) => i.get_input(),
         }
     }
     pub fn update(self: &mut Self, stepvalue: Option<f64>) {
         match self {
             Indicator::MovingAverage(i) => i.update(stepvalue),
             Indicator::Momentum(i) => i.update(stepvalue),
+            Indicator::StandardDeviation(i) => i.update(stepvalue),
         }
     }
     pub fn value(self: &Self) -> Option<f64> {
         match self {
             Indicator::MovingAverage(i) => i.value(),
             Indicator::Momentum(i) => i.value(),
+            Indicator::StandardDeviation(i) => i.value(),
         }
     }
     pub fn reset(self: &mut Self) {
         match self {
             Indicator::MovingAverage(i) => i.reset(),
             Indicator::Momentum(i) => i.reset(),
+            Indicator::StandardDeviation(i) => i.reset(),
         }
     }
 }
@@ -125,3 +131,46 @@ impl Momentum {
         self.operands = VecDeque::new();
     }
 }
+
+/// `StandardDeviation` is defined by the `length` it should look back and
+/// an `input: String` which can contain "price" to use the latest prices, or another string to give you
+/// the standard deviation of another `Indicator`.
+#[derive(Debug, Clone)]
+pub struct StandardDeviation {
+    pub length: usize,
+    pub input: String,
+    operands: VecDeque<Option<f64>>,
+}
+
+impl StandardDeviation {
+    pub fn new(length: usize, input: String) -> Self {
+        Self {
+            length,
+            input,
+            operands: VecDeque::new(),
+        }
+    }
+    fn value(self: &Self) -> Option<f64> {
+        let values: Vec<f64> = self.operands.iter().flatten().copied().collect();
+        if values.is_empty() {
+            return None;
+        }
+        let count = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / count;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count;
+        Some(variance.sqrt())
+    }
+
+    fn get_input(self: &Self) -> String {
+        (&self.input).to_string()
+    }
+
+    fn update(self: &mut Self, stepvalue: Option<f64>) {
+        self.operands.push_front(stepvalue);
+        self.operands.truncate(self.length);
+    }
+
+    fn reset(self: &mut Self) {
+        self.operands = VecDeque::new();
+    }
+}
@@ -0,0 +1,318 @@
+use anyhow::anyhow;
+use chrono::prelude::*;
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::account::OrderType;
+
+/// `AssetClass` is the broad kind of thing an `Instrument` is.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetClass {
+    Equity,
+    Future,
+    Option,
+    Fx,
+    Crypto,
+    Index,
+    Other,
+}
+
+/// `TradingSession` is the time of day, in UTC, an instrument trades. A
+/// session whose `end` is before its `start` runs overnight.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TradingSession {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl TradingSession {
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        let t = timestamp.time();
+        if self.start <= self.end {
+            self.start <= t && t < self.end
+        } else {
+            t >= self.start || t < self.end
+        }
+    }
+}
+
+/// `Instrument` describes how an asset trades. One lot is worth `multiplier`
+/// units of price (e.g. 10 for micro gold futures), prices move in steps of
+/// `tick_size`, orders must be a multiple of `lot_size` lots, and prices are
+/// quoted in `currency`. Orders only fill during `session`, if there is one,
+/// and cannot open or add to a short position unless `shortable`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Instrument {
+    pub asset: String,
+    #[serde(default = "default_asset_class")]
+    pub asset_class: AssetClass,
+    #[serde(default)]
+    pub exchange: Option<String>,
+    #[serde(default = "default_multiplier")]
+    pub multiplier: Decimal,
+    #[serde(default)]
+    pub tick_size: Option<Decimal>,
+    #[serde(default = "default_lot_size")]
+    pub lot_size: usize,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+    #[serde(default)]
+    pub session: Option<TradingSession>,
+    #[serde(default = "default_shortable")]
+    pub shortable: bool,
+}
+
+fn default_asset_class() -> AssetClass {
+    AssetClass::Other
+}
+
+fn default_multiplier() -> Decimal {
+    Decimal::new(1, 0)
+}
+
+fn default_lot_size() -> usize {
+    1
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+fn default_shortable() -> bool {
+    true
+}
+
+impl Instrument {
+    /// `new` is a shortable instrument with a multiplier of 1, no minimum
+    /// tick, a lot size of 1 and no session, quoted in USD.
+    pub fn new(asset: String) -> Self {
+        Self {
+            asset,
+            asset_class: default_asset_class(),
+            exchange: None,
+            multiplier: default_multiplier(),
+            tick_size: None,
+            lot_size: default_lot_size(),
+            currency: default_currency(),
+            session: None,
+            shortable: default_shortable(),
+        }
+    }
+
+    pub fn in_session(&self, timestamp: DateTime<Utc>) -> bool {
+        self.session.as_ref().is_none_or(|s| s.contains(timestamp))
+    }
+
+    /// `round_price` rounds `price` to a whole number of ticks, `up` or down.
+    pub fn round_price(&self, price: Decimal, up: bool) -> Decimal {
+        match self.tick_size {
+            Some(tick) if !tick.is_zero() => {
+                let ticks = price / tick;
+                let ticks = if up { ticks.ceil() } else { ticks.floor() };
+                ticks * tick
+            }
+            _ => price,
+        }
+    }
+
+    pub fn is_on_tick(&self, price: Decimal) -> bool {
+        self.round_price(price, false) == price
+    }
+}
+
+/// `TickPolicy` decides what happens to an order whose size is not a whole
+/// number of lots, or whose price is not a whole number of ticks. `Reject`
+/// rejects it. `Round` rounds the size towards zero, and rounds prices so
+/// the order is never more aggressive than asked: limits away from the
+/// market and stops further from it.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum TickPolicy {
+    #[default]
+    Reject,
+    Round,
+}
+
+/// `Instruments` is a registry of `Instrument`s by asset. Assets that are
+/// not registered behave as `Instrument::new`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Instruments {
+    pub instruments: HashMap<String, Instrument>,
+    pub tick_policy: TickPolicy,
+}
+
+/// `InstrumentFile` is the layout of a TOML instrument file: a list of
+/// `[[instrument]]` tables.
+#[derive(Debug, Deserialize)]
+struct InstrumentFile {
+    instrument: Vec<Instrument>,
+}
+
+/// `InstrumentRecord` is one row of a CSV instrument file, where the
+/// session is split into two columns.
+#[derive(Debug, Deserialize)]
+struct InstrumentRecord {
+    asset: String,
+    asset_class: Option<AssetClass>,
+    exchange: Option<String>,
+    currency: Option<String>,
+    multiplier: Option<Decimal>,
+    tick_size: Option<Decimal>,
+    lot_size: Option<usize>,
+    session_start: Option<NaiveTime>,
+    session_end: Option<NaiveTime>,
+    shortable: Option<bool>,
+}
+
+impl InstrumentRecord {
+    fn into_instrument(self) -> anyhow::Result<Instrument> {
+        let session = match (self.session_start, self.session_end) {
+            (Some(start), Some(end)) => Some(TradingSession { start, end }),
+            (None, None) => None,
+            _ => return Err(anyhow!("{} needs both session_start and session_end, or neither", self.asset)),
+        };
+        let mut instrument = Instrument::new(self.asset);
+        instrument.asset_class = self.asset_class.unwrap_or(instrument.asset_class);
+        instrument.exchange = self.exchange.filter(|e| !e.is_empty());
+        instrument.currency = self.currency.filter(|c| !c.is_empty()).unwrap_or(instrument.currency);
+        instrument.multiplier = self.multiplier.unwrap_or(instrument.multiplier);
+        instrument.tick_size = self.tick_size;
+        instrument.lot_size = self.lot_size.unwrap_or(instrument.lot_size);
+        instrument.session = session;
+        instrument.shortable = self.shortable.unwrap_or(instrument.shortable);
+        Ok(instrument)
+    }
+}
+
+impl Instruments {
+    pub fn register(&mut self, instrument: Instrument) {
+        self.instruments.insert(instrument.asset.clone(), instrument);
+    }
+
+    /// `from_toml` reads instruments from `[[instrument]]` tables, e.g.
+    /// ```
+    /// use rsbacktester::instrument::Instruments;
+    ///
+    /// let instruments = Instruments::from_toml(r#"
+    ///     [[instrument]]
+    ///     asset = "MGC"
+    ///     asset_class = "future"
+    ///     exchange = "COMEX"
+    ///     multiplier = 10
+    ///     tick_size = "0.1"
+    ///     session = { start = "23:00:00", end = "22:00:00" }
+    /// "#).unwrap();
+    /// assert!(instruments.get("MGC").unwrap().lot_size == 1);
+    /// ```
+    pub fn from_toml(contents: &str) -> anyhow::Result<Self> {
+        let file: InstrumentFile = toml::from_str(contents)?;
+        let mut instruments = Self::default();
+        for instrument in file.instrument {
+            instruments.register(instrument);
+        }
+        Ok(instruments)
+    }
+
+    /// `from_csv` reads instruments from a CSV file with an `asset` column
+    /// and any of `asset_class`, `exchange`, `currency`, `multiplier`,
+    /// `tick_size`, `lot_size`, `session_start`, `session_end` and `shortable`.
+    pub fn from_csv<R: std::io::Read>(reader: R) -> anyhow::Result<Self> {
+        let mut rdr = csv::Reader::from_reader(reader);
+        let mut instruments = Self::default();
+        for result in rdr.deserialize() {
+            let record: InstrumentRecord = result?;
+            instruments.register(record.into_instrument()?);
+        }
+        Ok(instruments)
+    }
+
+    /// `load` reads instruments from a `.toml` or `.csv` file.
+    pub fn load<P: AsRef<Path>>(path: &P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Self::from_toml(&std::fs::read_to_string(path)?),
+            Some("csv") => Self::from_csv(std::fs::File::open(path)?),
+            _ => Err(anyhow!("Instrument file {:?} should be .toml or .csv", path)),
+        }
+    }
+
+    /// `extend` registers every instrument in `other`, replacing any
+    /// already registered for the same asset.
+    pub fn extend(&mut self, other: Instruments) {
+        for (_, instrument) in other.instruments {
+            self.register(instrument);
+        }
+    }
+
+    pub fn in_session(&self, asset: &str, timestamp: DateTime<Utc>) -> bool {
+        self.get(asset).is_none_or(|i| i.in_session(timestamp))
+    }
+
+    pub fn is_shortable(&self, asset: &str) -> bool {
+        self.get(asset).is_none_or(|i| i.shortable)
+    }
+
+    pub fn get(&self, asset: &str) -> Option<&Instrument> {
+        self.instruments.get(asset)
+    }
+
+    /// `currency` is the currency `asset` is quoted and settles in.
+    pub fn currency(&self, asset: &str) -> String {
+        self.get(asset).map(|i| i.currency.clone()).unwrap_or_else(default_currency)
+    }
+
+    pub fn multiplier(&self, asset: &str) -> Decimal {
+        self.get(asset).map(|i| i.multiplier).unwrap_or(Decimal::new(1, 0))
+    }
+
+    /// `notional` is the value of `lots` of `asset` at `price`.
+    pub fn notional(&self, asset: &str, lots: isize, price: Decimal) -> Decimal {
+        price * Decimal::new(lots as i64, 0) * self.multiplier(asset)
+    }
+
+    /// `conform` checks an order's size and prices against the instrument
+    /// for `asset`, returning them rounded under `TickPolicy::Round`, or an
+    /// error explaining why the order should be rejected.
+    pub fn conform(&self, asset: &str, lots: isize, order_type: &OrderType) -> Result<(isize, OrderType), String> {
+        let instrument = match self.get(asset) {
+            Some(i) => i,
+            None => return Ok((lots, order_type.clone())),
+        };
+        let round = self.tick_policy == TickPolicy::Round;
+
+        let lot_size = instrument.lot_size.max(1) as isize;
+        let mut lots = lots;
+        if lots % lot_size != 0 {
+            if !round {
+                return Err(format!("{} lots of {} is not a multiple of the lot size {}", lots, asset, lot_size));
+            }
+            lots -= lots % lot_size;
+            if lots == 0 {
+                return Err(format!("Order for {} rounds to zero lots at lot size {}", asset, lot_size));
+            }
+        }
+
+        let buy = lots > 0;
+        let conform_price = |price: Decimal, up: bool| -> Result<Decimal, String> {
+            if instrument.is_on_tick(price) {
+                Ok(price)
+            } else if round {
+                Ok(instrument.round_price(price, up))
+            } else {
+                Err(format!("Price {} for {} is not a multiple of the tick size {}", price, asset, instrument.tick_size.unwrap_or_default()))
+            }
+        };
+        let order_type = match order_type {
+            OrderType::Market => OrderType::Market,
+            OrderType::Limit(p) => OrderType::Limit(conform_price(*p, !buy)?),
+            OrderType::Stop(p) => OrderType::Stop(conform_price(*p, buy)?),
+            OrderType::StopLimit { stop, limit } => OrderType::StopLimit {
+                stop: conform_price(*stop, buy)?,
+                limit: conform_price(*limit, !buy)?,
+            },
+        };
+        Ok((lots, order_type))
+    }
+}
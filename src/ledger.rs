@@ -0,0 +1,167 @@
+use chrono::prelude::*;
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+
+use crate::account::Fill;
+use crate::instrument::Instruments;
+use crate::Tick;
+
+/// `RoundTrip` is a trade from flat to flat in one asset, made up of one
+/// or more fills. `lots` is the largest position held, signed by direction.
+/// `realized_pnl` is net of `fees`. `mae` and `mfe` are the maximum adverse
+/// and favourable excursions of the mid price from `entry_price` while the
+/// trade was open, in price units per lot and never negative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoundTrip {
+    pub asset: String,
+    pub lots: isize,
+    pub entry_time: DateTime<Utc>,
+    pub exit_time: DateTime<Utc>,
+    pub holding_period: chrono::Duration,
+    pub entry_price: Decimal,
+    pub exit_price: Decimal,
+    pub fees: Decimal,
+    pub realized_pnl: Decimal,
+    pub mae: Decimal,
+    pub mfe: Decimal,
+}
+
+/// `OpenTrip` accumulates fills for a round trip that has not closed yet.
+struct OpenTrip {
+    lots: isize,
+    max_lots: isize,
+    entry_time: DateTime<Utc>,
+    entry_cost: Decimal,
+    entry_lots: isize,
+    exit_value: Decimal,
+    exit_lots: isize,
+    fees: Decimal,
+    pnl: Decimal,
+}
+
+/// `round_trips` pairs up `fills` into closed `RoundTrip`s, in the order
+/// they closed. A fill that takes a position through zero closes one trip
+/// and opens another, with its fee split between them. `ticks` are used to
+/// find each trip's MAE and MFE, and `instruments` for contract multipliers.
+pub fn round_trips(fills: &[Fill], ticks: &[Tick], instruments: &Instruments) -> Vec<RoundTrip> {
+    let mut open: HashMap<String, OpenTrip> = HashMap::new();
+    let mut trips = vec![];
+    for fill in fills {
+        let mut remaining = fill.lots;
+        while remaining != 0 {
+            let trip = open.entry(fill.asset.clone()).or_insert_with(|| OpenTrip {
+                lots: 0,
+                max_lots: 0,
+                entry_time: fill.timestamp,
+                entry_cost: Decimal::new(0, 0),
+                entry_lots: 0,
+                exit_value: Decimal::new(0, 0),
+                exit_lots: 0,
+                fees: Decimal::new(0, 0),
+                pnl: Decimal::new(0, 0),
+            });
+            let lots = if trip.lots != 0 && trip.lots.signum() != remaining.signum() {
+                remaining.signum() * remaining.abs().min(trip.lots.abs())
+            } else {
+                remaining
+            };
+            let fee = fill.fee * Decimal::new(lots as i64, 0) / Decimal::new(fill.lots as i64, 0);
+            trip.fees += fee;
+            trip.pnl -= fee;
+            if trip.lots == 0 || trip.lots.signum() == lots.signum() {
+                trip.entry_cost += fill.price * Decimal::new(lots as i64, 0);
+                trip.entry_lots += lots;
+            } else {
+                let entry_price = trip.entry_cost / Decimal::new(trip.entry_lots as i64, 0);
+                trip.pnl += instruments.notional(&fill.asset, lots, entry_price - fill.price);
+                trip.exit_value += fill.price * Decimal::new(lots as i64, 0);
+                trip.exit_lots += lots;
+            }
+            trip.lots += lots;
+            if trip.lots.abs() > trip.max_lots.abs() {
+                trip.max_lots = trip.lots;
+            }
+            remaining -= lots;
+
+            if trip.lots == 0 {
+                let trip = open.remove(&fill.asset).unwrap();
+                trips.push(close_trip(&fill.asset, trip, fill.timestamp, ticks));
+            }
+        }
+    }
+    trips
+}
+
+fn close_trip(asset: &str, trip: OpenTrip, exit_time: DateTime<Utc>, ticks: &[Tick]) -> RoundTrip {
+    let entry_price = trip.entry_cost / Decimal::new(trip.entry_lots as i64, 0);
+    let exit_price = trip.exit_value / Decimal::new(trip.exit_lots as i64, 0);
+    let long = trip.max_lots > 0;
+    let mut mae = Decimal::new(0, 0);
+    let mut mfe = Decimal::new(0, 0);
+    for tick in ticks.iter().filter(|t| t.asset == asset && t.timestamp >= trip.entry_time && t.timestamp <= exit_time) {
+        let mid = (tick.bid + tick.ask) / Decimal::new(2, 0);
+        let gain = if long { mid - entry_price } else { entry_price - mid };
+        mfe = mfe.max(gain);
+        mae = mae.max(-gain);
+    }
+    RoundTrip {
+        asset: asset.to_string(),
+        lots: trip.max_lots,
+        entry_time: trip.entry_time,
+        exit_time,
+        holding_period: exit_time - trip.entry_time,
+        entry_price,
+        exit_price,
+        fees: trip.fees,
+        realized_pnl: trip.pnl,
+        mae,
+        mfe,
+    }
+}
+
+/// `TradeStats` summarises a list of `RoundTrip`s. A trip with zero P&L
+/// counts as neither a win nor a loss. `average_loss` and `gross_loss` are
+/// negative.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TradeStats {
+    pub trades: usize,
+    pub wins: usize,
+    pub losses: usize,
+    pub win_rate: Option<f64>,
+    pub gross_profit: Decimal,
+    pub gross_loss: Decimal,
+    pub profit_factor: Option<f64>,
+    pub expectancy: Option<Decimal>,
+    pub average_win: Option<Decimal>,
+    pub average_loss: Option<Decimal>,
+}
+
+impl TradeStats {
+    pub fn from_round_trips(trips: &[RoundTrip]) -> Self {
+        let wins: Vec<Decimal> = trips.iter().map(|t| t.realized_pnl).filter(|p| p.is_sign_positive() && !p.is_zero()).collect();
+        let losses: Vec<Decimal> = trips.iter().map(|t| t.realized_pnl).filter(|p| p.is_sign_negative() && !p.is_zero()).collect();
+        let gross_profit: Decimal = wins.iter().sum();
+        let gross_loss: Decimal = losses.iter().sum();
+        let total = gross_profit + gross_loss;
+        Self {
+            trades: trips.len(),
+            wins: wins.len(),
+            losses: losses.len(),
+            win_rate: if trips.is_empty() { None } else { Some(wins.len() as f64 / trips.len() as f64) },
+            gross_profit,
+            gross_loss,
+            profit_factor: if gross_loss.is_zero() { None } else { (gross_profit / -gross_loss).to_f64() },
+            expectancy: average(total, trips.len()),
+            average_win: average(gross_profit, wins.len()),
+            average_loss: average(gross_loss, losses.len()),
+        }
+    }
+}
+
+fn average(total: Decimal, count: usize) -> Option<Decimal> {
+    if count == 0 {
+        None
+    } else {
+        Some(total / Decimal::from(count))
+    }
+}
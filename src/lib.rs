@@ -1,16 +1,44 @@
-#![allow(dead_code)]
+#![allow(dead_code, clippy::needless_arbitrary_self_type)]
 use chrono::prelude::*;
 use hashbrown::HashMap;
 use rust_decimal::prelude::*;
 use serde::Deserialize;
 use std::path::Path;
+use std::sync::Arc;
 
 pub mod indicators;
 pub mod position;
 pub mod account;
+pub mod bar;
+pub mod builder;
+pub mod commission;
+pub mod financing;
+pub mod fx;
+pub mod instrument;
+pub mod ledger;
+pub mod loader;
+pub mod margin;
+pub mod report;
+pub mod resample;
+pub mod runner;
+pub mod slippage;
+pub mod strategies;
+pub mod strategy;
+pub mod validation;
 pub mod tests;
 
-use account::Account;
+use account::{Account, Fill, OrderId, OrderType};
+use bar::{Bar, BarFill};
+use commission::{CommissionModel, Commissions};
+use financing::Financing;
+use instrument::Instrument;
+use ledger::{RoundTrip, TradeStats};
+use loader::{CsvSchema, LoadError};
+use margin::{MarginCall, MarginCallPolicy};
+use report::{BacktestReport, EquityPoint};
+use slippage::{NoSlippage, SlippageModel};
+use strategy::{BacktestResult, Context, Strategy};
+use validation::{DataQualityReport, Validation};
 
 /// `Tick` holds a timestamp, an asset, and a bid and ask price
 /// ```
@@ -45,16 +73,44 @@ pub struct TS {
 }
 
 /// `Mode` lets you set if this engine is running live or backtesting. Only backtesting works for now.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum Mode {
     Live,
     Backtest,
 }
 
+/// `ExecutionTiming` decides which tick an order is filled against.
+/// `SameTick` fills against the tick the strategy has just seen, which
+/// leaks that tick into the fill. With bars, only a market order filled at
+/// the `BarFill::Close` can fill on the bar just seen; anything else would
+/// trade inside a bar that is already over, so it waits for the next bar as
+/// under `NextTick`. `NextTick` fills against the first tick
+/// after it, and `Latency` against the first later tick at or after the
+/// decision time plus the given delay.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ExecutionTiming {
+    SameTick,
+    #[default]
+    NextTick,
+    Latency(chrono::Duration),
+}
+
 /// `Engine` is the main struct in `rsbacktester`, holding
 /// account info, current time, history of prices, current index
 /// and last price for each asset, any signals for trading, indicators
-/// that have been registered, and the mode (currently just backtesting).
+/// that have been registered, the mode (currently just backtesting),
+/// an optional cap on how many lots of an order fill on any one tick,
+/// the commission and slippage applied to fills, and when orders are filled.
+/// `last_marked` is the index of the last tick indicators and prices were
+/// updated with, i.e. the last tick a strategy could have seen, and
+/// `equity_curve` has the total equity recorded after every step. Margin
+/// calls are handled by `margin_call_policy` and recorded in `margin_calls`.
+/// Interest and borrow fees are accrued under `financing` once a day, on the
+/// first tick of each new UTC date after `last_accrual`. When the engine is
+/// driven by `bars`, `prices` holds each bar's close as a tick, and orders
+/// are filled within the bar according to `bar_fill`. `data_quality` is the
+/// report from validating the data, if it was.
 #[derive(Debug, Clone)]
 pub struct Engine {
     pub acct: account::Account,
@@ -65,6 +121,19 @@ pub struct Engine {
     pub indicators: hashbrown::HashMap<String, indicators::Indicator>,
     pub last_price: hashbrown::HashMap<String, Decimal>,
     pub mode: Mode,
+    pub max_fill_lots: Option<usize>,
+    pub commissions: Commissions,
+    pub slippage: Arc<dyn SlippageModel>,
+    pub execution: ExecutionTiming,
+    pub last_marked: Option<i64>,
+    pub equity_curve: Vec<EquityPoint>,
+    pub margin_call_policy: MarginCallPolicy,
+    pub margin_calls: Vec<MarginCall>,
+    pub financing: Financing,
+    pub last_accrual: Option<NaiveDate>,
+    pub bars: Vec<Bar>,
+    pub bar_fill: BarFill,
+    pub data_quality: Option<DataQualityReport>,
 }
 
 unsafe impl Send for Engine {}
@@ -79,14 +148,232 @@ pub struct Signal {
     pub duration: Option<i32>,
 }
 
+// fills are only applied in currencies `last_price` has an FX rate for,
+// and prices are never dropped, so the account can always be valued
+const NO_FX_RATE: &str = "no FX rate for a currency the account holds";
+
 impl Engine {
     pub fn step(self: &mut Engine) {
+        self.accrue();
+        self.match_orders();
+        self.mark();
+        self.update_account_orders();
+        self.check_margin_call();
+        self.record_equity();
+        self.index += 1;
+    }
+
+    fn record_equity(&mut self) {
+        let point = EquityPoint{timestamp: self.time, equity: self.equity()};
+        self.equity_curve.push(point);
+    }
+
+    /// `check_margin_call` records a `MarginCall` if equity is below the
+    /// maintenance requirement, placing liquidation orders as
+    /// `margin_call_policy` says. While liquidation orders from the last
+    /// call are still open, no new call is made.
+    fn check_margin_call(&mut self) -> Option<MarginCall> {
+        let liquidating = self.margin_calls.last().is_some_and(|c| {
+            c.orders.iter().any(|id| self.acct.order(*id).is_some_and(|o| o.is_open()))
+        });
+        if liquidating {
+            return None;
+        }
+        let equity = self.equity();
+        let maintenance_requirement = self.acct.margin_requirement(&self.last_price, true).expect(NO_FX_RATE);
+        if equity >= maintenance_requirement {
+            return None;
+        }
+
+        // losses and requirements in the base currency, to rank and count them together
+        let mut positions: Vec<(String, isize, Decimal, Decimal)> = self.acct.portfolio.values().map(|pos| {
+            let price = self.last_price.get(&pos.asset).copied().unwrap_or(pos.cost_basis);
+            let rate = self.acct.margin.requirement(&pos.asset).maintenance;
+            let currency = self.acct.instruments.currency(&pos.asset);
+            let to_base = |amount| self.acct.to_base(amount, &currency, &self.last_price).expect(NO_FX_RATE);
+            let requirement = to_base(rate * self.acct.instruments.notional(&pos.asset, pos.lots, price).abs());
+            (pos.asset.clone(), pos.lots, to_base(self.acct.unrealized_pnl(&pos.asset, price)), requirement)
+        }).collect();
+        positions.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0)));
+
+        let mut orders = vec![];
+        let mut remaining = maintenance_requirement;
+        for (asset, lots, _, requirement) in positions {
+            match self.margin_call_policy {
+                MarginCallPolicy::Notify => break,
+                MarginCallPolicy::LiquidateLargestLoss if equity >= remaining => break,
+                _ => {}
+            }
+            let id = self.acct.submit_order(asset, -lots, OrderType::Market);
+            if let Some(order) = self.acct.order_mut(id) {
+                order.not_before_index = self.index + 1;
+            }
+            orders.push(id);
+            remaining -= requirement;
+        }
+
+        let call = MarginCall{timestamp: self.time, equity, maintenance_requirement, orders};
+        self.margin_calls.push(call.clone());
+        Some(call)
+    }
+
+    /// `report` computes a `BacktestReport` from the whole equity curve,
+    /// with a risk-free rate of zero.
+    pub fn report(&self) -> BacktestReport {
+        BacktestReport::from_equity_curve(&self.equity_curve, 0.)
+    }
+
+    /// `round_trips` pairs up every fill in the account's ledger into closed
+    /// round-trip trades.
+    pub fn round_trips(&self) -> Vec<RoundTrip> {
+        ledger::round_trips(&self.acct.fills, &self.prices.ticks, &self.acct.instruments)
+    }
+
+    /// `accrue` posts interest and borrow fees for every day between
+    /// `last_accrual` and the date of the tick at `self.index`, using the
+    /// balances held before that tick. Ticks that go back in time are ignored.
+    fn accrue(&mut self) {
+        let date = match self.prices.ticks.get(self.index as usize) {
+            Some(t) => t.timestamp.date_naive(),
+            None => return,
+        };
+        let last = match self.last_accrual {
+            Some(last) => last,
+            None => {
+                self.last_accrual = Some(date);
+                return;
+            }
+        };
+        if date > last {
+            let timestamp = self.prices.ticks[self.index as usize].timestamp;
+            let days = (date - last).num_days();
+            self.acct.accrue(&self.financing, days, &self.last_price, timestamp).expect(NO_FX_RATE);
+            self.last_accrual = Some(date);
+        }
+    }
+
+    /// `mark` brings time, indicators and the last price up to date with
+    /// the tick at `self.index`, without touching the account.
+    fn mark(&mut self) {
         let iv = self.indicator_values();
         self.update_indicators(iv);
         let tick = &self.prices.ticks[self.index as usize];
+        self.time = tick.timestamp;
+        self.last_marked = Some(self.index);
         self.last_price.insert(tick.asset.clone(), (tick.ask.checked_add(tick.bid)).unwrap().checked_div(Decimal::new(2,0)).unwrap());
-        self.update_account_orders();
-        self.index += 1;
+    }
+
+    /// `run` drives `strategy` over every remaining tick in `self.prices`
+    /// and returns a `BacktestResult`. For each tick the engine first applies
+    /// the orders that fill on it, calling `Strategy::on_fill` for each one,
+    /// then updates indicators and last prices, then calls
+    /// `Strategy::on_tick`, then applies any orders that filled as the
+    /// strategy placed them. Once the last tick is fully processed it calls
+    /// `Strategy::on_end`.
+    pub fn run<S: Strategy>(&mut self, strategy: &mut S) -> BacktestResult {
+        let starting_equity = self.equity();
+        let start = self.index;
+        let curve_start = self.equity_curve.len();
+        let len = self.prices.ticks.len() as i64;
+        let mut fills = vec![];
+
+        strategy.on_start(&mut Context::new(self));
+        while self.index < len {
+            let tick = self.prices.ticks[self.index as usize].clone();
+            self.accrue();
+            self.match_orders();
+            for fill in self.update_account_orders() {
+                strategy.on_fill(&fill, &mut Context::new(self));
+                fills.push(fill);
+            }
+            self.mark();
+            strategy.on_tick(&tick, &mut Context::new(self));
+            for fill in self.update_account_orders() {
+                strategy.on_fill(&fill, &mut Context::new(self));
+                fills.push(fill);
+            }
+            if let Some(call) = self.check_margin_call() {
+                strategy.on_margin_call(&call, &mut Context::new(self));
+            }
+            self.record_equity();
+            self.index += 1;
+        }
+        let before_end: Vec<OrderId> = self.acct.orders.iter().map(|o| o.id).collect();
+        strategy.on_end(&mut Context::new(self));
+        if self.index > start {
+            for fill in self.settle_end_orders(&before_end) {
+                strategy.on_fill(&fill, &mut Context::new(self));
+                fills.push(fill);
+            }
+        }
+
+        let trades = ledger::round_trips(&fills, &self.prices.ticks, &self.acct.instruments);
+        let mut curve = vec![EquityPoint{timestamp: self.prices.ticks.get(start as usize).map(|t| t.timestamp).unwrap_or(self.time), equity: starting_equity}];
+        curve.extend_from_slice(&self.equity_curve[curve_start..]);
+        BacktestResult {
+            starting_equity,
+            final_equity: self.equity(),
+            ticks_processed: (self.index - start).max(0) as usize,
+            fills,
+            trade_stats: TradeStats::from_round_trips(&trades),
+            trades,
+            report: BacktestReport::from_equity_curve(&curve, 0.),
+        }
+    }
+
+    /// `settle_end_orders` fills the open orders not in `before`, i.e. those
+    /// placed in `Strategy::on_end`, against the final tick whatever the
+    /// `execution` timing, as there is no later tick for them to wait for.
+    /// The last point of the equity curve is brought up to date.
+    fn settle_end_orders(&mut self, before: &[OrderId]) -> Vec<Fill> {
+        let last = self.index - 1;
+        for order in self.acct.orders.iter_mut().filter(|o| o.is_open() && !before.contains(&o.id)) {
+            order.not_before_index = last;
+            order.not_before_time = None;
+        }
+        self.index = last;
+        self.match_orders_where(|o| !before.contains(&o.id));
+        let fills = self.update_account_orders();
+        self.index = last + 1;
+        let equity = self.equity();
+        if let Some(point) = self.equity_curve.last_mut() {
+            point.equity = equity;
+        }
+        fills
+    }
+
+    /// `set_commission_model` sets the commission charged on every asset
+    /// without its own override.
+    pub fn set_commission_model<C: CommissionModel + 'static>(&mut self, model: C) {
+        self.commissions.default = Arc::new(model);
+    }
+
+    /// `set_asset_commission_model` overrides the commission charged on `asset`.
+    pub fn set_asset_commission_model<C: CommissionModel + 'static>(&mut self, asset: String, model: C) {
+        self.commissions.per_asset.insert(asset, Arc::new(model));
+    }
+
+    /// `register_instrument` sets the contract multiplier, tick size, lot
+    /// size and currency used for `instrument.asset`.
+    pub fn register_instrument(&mut self, instrument: Instrument) {
+        self.acct.instruments.register(instrument);
+    }
+
+    pub fn instrument(&self, asset: &str) -> Option<&Instrument> {
+        self.acct.instruments.get(asset)
+    }
+
+    /// `load_instruments` registers every instrument in a `.toml` or `.csv`
+    /// file; see `Instruments::from_toml` and `Instruments::from_csv`.
+    pub fn load_instruments<P: AsRef<Path>>(&mut self, path: &P) -> anyhow::Result<()> {
+        let instruments = instrument::Instruments::load(path)?;
+        self.acct.instruments.extend(instruments);
+        Ok(())
+    }
+
+    /// `set_slippage_model` sets how far market and stop fills move away from the touch.
+    pub fn set_slippage_model<S: SlippageModel + 'static>(&mut self, model: S) {
+        self.slippage = Arc::new(model);
     }
 
     pub fn register_indicator(
@@ -97,7 +384,7 @@ impl Engine {
         self.indicators.insert(name, indicator);
     }
 
-    fn indicator_values(self: &Engine) -> HashMap<String, Option<f64>> {
+    pub fn indicator_values(self: &Engine) -> HashMap<String, Option<f64>> {
         let mut values = HashMap::new();
         for (name, ind) in &self.indicators {
             let v = ind.value();
@@ -106,144 +393,277 @@ impl Engine {
         values
     }
 
+    /// `update_indicators` steps every indicator with its input: "price" is
+    /// the mid price, "open", "high", "low", "close" and "volume" come from
+    /// the current bar (or are the mid price, and no volume, without bars),
+    /// and anything else is the value of the indicator with that name.
     pub fn update_indicators(self: &mut Engine, ind_values: HashMap<String, Option<f64>>) {
-        for (_, indicator) in &mut self.indicators {
-            if &indicator.get_input() == "price" {
-                let stepvaluetick = self.prices.ticks[self.index as usize].clone();
-                let stepvaluesum = stepvaluetick.ask.checked_add(stepvaluetick.bid);
-                let stepvalue = stepvaluesum.unwrap().checked_div(Decimal::new(2, 0));
-                let v: Option<f64> = stepvalue.expect("Decimal was screwy").to_f64();
-                indicator.update(v);
-            } else {
-                let v = ind_values[&indicator.get_input()];
-                indicator.update(v);
+        let tick = &self.prices.ticks[self.index as usize];
+        let mid = tick.ask.checked_add(tick.bid).and_then(|sum| sum.checked_div(Decimal::new(2, 0)));
+        let bar = self.bars.get(self.index as usize);
+        for indicator in self.indicators.values_mut() {
+            let input = indicator.get_input();
+            match input.as_str() {
+                "price" | "open" | "high" | "low" | "close" | "volume" => {
+                    let v = match bar {
+                        Some(bar) if input != "price" => bar.field(&input),
+                        None if input == "volume" => None,
+                        _ => mid,
+                    };
+                    indicator.update(v.and_then(|v| v.to_f64()));
+                }
+                _ => {
+                    let v = ind_values[&input];
+                    indicator.update(v);
+                }
             }
         }
     }
 
-    pub fn place_order(self: &mut Self, asset: String, lots: isize) {
-        self.acct.submit_order(asset, lots);
+    /// `place_order` submits a market order for `lots` of `asset`.
+    pub fn place_order(self: &mut Self, asset: String, lots: isize) -> OrderId {
+        self.submit_order(asset, lots, OrderType::Market)
+    }
+
+    /// `submit_order` submits an order of any `OrderType`. In backtest mode
+    /// it is checked against the current tick straight away if `execution`
+    /// allows, and any order left pending is checked again on each `step`.
+    pub fn submit_order(&mut self, asset: String, lots: isize, order_type: OrderType) -> OrderId {
+        let id = self.acct.submit_order(asset, lots, order_type);
+        self.set_arrival(id);
         if self.mode == Mode::Backtest {
-            for order in &mut self.acct.orders {
-                if order.state == account::OrderState::Pending {
-                    order.state = account::OrderState::Executed;
-                    let last_tick = &self.prices.ticks[self.index as usize];
-                    if lots > 0 {
-                        order.cost_basis = Some(last_tick.ask);
-                    } else {
-                        order.cost_basis = Some(last_tick.bid);
-                    }
+            self.match_orders();
+        }
+        id
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId) -> anyhow::Result<()> {
+        self.acct.cancel_order(id)
+    }
+
+    /// `modify_order` replaces the size and type of an open order, which
+    /// then arrives again as if newly submitted.
+    pub fn modify_order(&mut self, id: OrderId, lots: isize, order_type: OrderType) -> anyhow::Result<()> {
+        self.acct.modify_order(id, lots, order_type)?;
+        self.set_arrival(id);
+        if self.mode == Mode::Backtest {
+            self.match_orders();
+        }
+        Ok(())
+    }
+
+    /// `set_arrival` sets the earliest tick order `id` can fill on, according
+    /// to `execution` and the last tick the strategy could have seen.
+    fn set_arrival(&mut self, id: OrderId) {
+        let next = self.last_marked.map(|i| i + 1).unwrap_or(0);
+        let at_close = self.bar_fill == BarFill::Close && self.acct.order(id).is_some_and(|o| o.order_type == OrderType::Market);
+        let (index, time) = match self.execution {
+            ExecutionTiming::SameTick if !self.bars.is_empty() && !at_close => (next, None),
+            ExecutionTiming::SameTick => (0, None),
+            ExecutionTiming::NextTick => (next, None),
+            ExecutionTiming::Latency(delay) => (next, Some(self.time + delay)),
+        };
+        if let Some(order) = self.acct.order_mut(id) {
+            order.not_before_index = index;
+            order.not_before_time = time;
+        }
+    }
+
+    /// `match_orders` fills any open orders that the tick at `self.index`
+    /// touches during their instrument's trading session, up to `max_fill_lots` per order, queueing a `Fill` for each.
+    /// Orders that execute as market orders are slipped by the slippage
+    /// model, rounded against the order to the asset's tick size; limit
+    /// orders fill at the touch.
+    fn match_orders(&mut self) {
+        self.match_orders_where(|_| true);
+    }
+
+    /// `match_orders_where` is `match_orders` for only the orders `include`
+    /// picks out.
+    fn match_orders_where<F: Fn(&account::Order) -> bool>(&mut self, include: F) {
+        let tick = match self.prices.ticks.get(self.index as usize) {
+            Some(t) => t,
+            None => return,
+        };
+        for order in &mut self.acct.orders {
+            if !order.is_open() || self.index < order.not_before_index || !include(order) {
+                continue;
+            }
+            if order.not_before_time.is_some_and(|t| tick.timestamp < t) {
+                continue;
+            }
+            if !self.acct.instruments.in_session(&order.asset, tick.timestamp) {
+                continue;
+            }
+            let price = match self.bars.get(self.index as usize) {
+                Some(bar) => order.evaluate_bar(bar, &self.bar_fill),
+                None => order.evaluate(tick),
+            };
+            if let Some(price) = price {
+                let mut lots = order.remaining();
+                if let Some(max) = self.max_fill_lots {
+                    // pending fills have all come from this tick, so count them against the cap
+                    let filled: isize = self.acct.pending_fills.iter().filter(|f| f.order_id == order.id).map(|f| f.lots.abs()).sum();
+                    lots = lots.signum() * lots.abs().min((max as isize - filled).max(0));
+                }
+                if lots == 0 {
+                    continue;
                 }
+                let price = if let OrderType::Limit(_) = order.order_type {
+                    price
+                } else {
+                    let slip = self.slippage.slippage(tick, lots, &self.indicators);
+                    let price = if lots > 0 { price + slip } else { price - slip };
+                    match self.acct.instruments.get(&order.asset) {
+                        Some(i) if !slip.is_zero() => i.round_price(price, lots > 0),
+                        _ => price,
+                    }
+                };
+                order.record_fill(lots, price);
+                let multiplier = self.acct.instruments.multiplier(&order.asset);
+                let fee = self.commissions.commission(&order.asset, lots, price, multiplier);
+                self.acct.pending_fills.push(Fill{order_id: order.id, timestamp: tick.timestamp, asset: order.asset.clone(), lots, price, fee});
             }
         }
     }
 
-    pub fn update_account_orders(self: &mut Self) {
+    /// `update_account_orders` applies pending fills to the account and drops
+    /// finished orders, returning each `Fill` that was applied. A fill the
+    /// account does not have the margin for rejects its order, which is
+    /// moved to `acct.rejected`.
+    pub fn update_account_orders(self: &mut Self) -> Vec<Fill> {
+        let mut fills = vec![];
+        for fill in std::mem::take(&mut self.acct.pending_fills) {
+            match self.acct.apply_fill(&fill, &self.last_price) {
+                Ok(()) => fills.push(fill),
+                Err(e) => self.acct.reject_fill(&fill, e.to_string()),
+            }
+        }
         for i in (0..self.acct.orders.len()).rev() {
-            let order = &self.acct.orders[i];
-            match order.state {
-                account::OrderState::Executed => {
-                    let p = position::Position{asset: order.asset.clone(), lots: order.lots, cost_basis: order.cost_basis.unwrap()};
-                    let result = self.acct.position(p);
-                    if result.is_err() {
-                        println!("self.acct.position failed! {:?}", result);
-                    }
-                },
-                account::OrderState::Rejected => {
-                    println!("Order rejected: {:?}", order);
-                    self.acct.orders.remove(i);
-                }
-                account::OrderState::Pending => {
-                },
+            if self.acct.orders[i].state == account::OrderState::Rejected {
+                let order = self.acct.orders.remove(i);
+                self.acct.rejected.push(order);
             }
         }
         self.acct.clear_executed();
+        fills
     }
 
     pub fn reset(self: &mut Engine, cash: f64) {
         self.acct.cash = Decimal::from_f64(cash).unwrap();
+        self.acct.balances = HashMap::new();
         self.acct.portfolio = HashMap::new();
+        self.acct.realized_pnl = HashMap::new();
+        self.acct.short_collateral = HashMap::new();
+        self.acct.orders = vec![];
+        self.acct.pending_fills = vec![];
+        self.margin_calls = vec![];
         self.index = 0;
+        self.last_marked = None;
+        self.last_accrual = None;
+        self.equity_curve = vec![];
         for i in self.indicators.values_mut() {
             i.reset();
         }
     }
 
+    /// `unrealized_pnl` is the open P&L of every position, marked at
+    /// `last_price`, in the base currency.
+    pub fn unrealized_pnl(&self) -> Decimal {
+        self.acct.total_unrealized_pnl(&self.last_price).expect(NO_FX_RATE)
+    }
+
+    /// `equity` is the account's equity in its base currency, converted at
+    /// the latest mid prices of any FX pairs in the data.
     pub fn equity(self: &Self) -> Decimal {
-        let mut total_equity = Decimal::new(0, 0);
-        for (asset, pos) in &self.acct.portfolio {
-            let equity = self.last_price[asset].checked_mul(Decimal::new(pos.lots as i64, 0)).unwrap_or(Decimal::new(0,0));
-            total_equity = total_equity.checked_add(equity).unwrap();
-        }
-        total_equity = total_equity.checked_add(self.acct.cash).unwrap();
-        total_equity
+        self.acct.equity(&self.last_price).expect(NO_FX_RATE)
     }
 }
 
-#[derive(Debug, Deserialize)]
-struct Record {
-    #[serde(rename = "Date")]
-    pub date: String,
-    #[serde(rename = "Time")]
-    pub time: String,
-    #[serde(rename = "Asset")]
-    pub asset: String,
-    #[serde(rename = "Bid")]
-    pub bid: String,
-    #[serde(rename = "Ask")]
-    pub ask: String,
+fn init_acct(cash: i64) -> Account {
+    Account::new(Decimal::from(cash))
 }
 
-fn init_acct(cash: i64) -> Account {
-    Account {
-        cash: Decimal::from(cash),
-        portfolio: HashMap::new(),
-        trades: vec![],
-        orders: vec![],
+/// `init_engine` is the main way to create a new engine. Pass in a `path`
+/// to the tick data and a starting value for `cash`. It panics if the data
+/// cannot be loaded; see `try_init_engine`.
+pub fn init_engine<P: AsRef<Path>>(path: &P, cash: i64) -> Engine {
+    try_init_engine(path, cash).expect("could not load prices")
+}
+
+/// `try_init_engine` is `init_engine`, returning a `LoadError` if the data
+/// cannot be read or parsed, or has no ticks.
+pub fn try_init_engine<P: AsRef<Path>>(path: &P, cash: i64) -> Result<Engine, LoadError> {
+    let prices = CsvSchema::default().load(path)?;
+    if prices.ticks.is_empty() {
+        return Err(LoadError::Empty);
     }
+    Ok(new_engine(prices, vec![], cash))
 }
 
-fn record_to_tick(r: &Record) -> anyhow::Result<Tick> {
-    let d = NaiveDate::parse_from_str(&r.date, "%Y/%m/%d")?;
-    let t = NaiveTime::parse_from_str(&r.time, "%H:%M:%S%.f")?;
-    let dt = NaiveDateTime::new(d, t);
-    let utc_dt: DateTime<Utc> = DateTime::from_utc(dt, Utc);
-
-    let ask: Decimal = Decimal::from_str(&r.ask)?;
-    let bid: Decimal = Decimal::from_str(&r.bid)?;
-    Ok(Tick {
-        timestamp: utc_dt,
-        asset: r.asset.clone(),
-        ask: ask,
-        bid: bid,
-    })
+/// `init_engine_with_schema` creates an engine from tick data laid out as
+/// `schema` describes, e.g. a vendor file with other column names. It
+/// panics if the data cannot be loaded or has no ticks; see
+/// `try_init_engine_with_schema`.
+pub fn init_engine_with_schema<P: AsRef<Path>>(path: &P, schema: &CsvSchema, cash: i64) -> Engine {
+    let (engine, _) = try_init_engine_with_schema(path, schema, &Validation::default(), cash).expect("could not load prices");
+    engine
 }
 
-fn init_prices<P: AsRef<Path>>(path: &P) -> anyhow::Result<TS> {
-    let mut rdr = csv::Reader::from_path(path)?;
-    let mut ticks = vec![];
-    for result in rdr.deserialize() {
-        let record: Record = result?;
-        let tick = record_to_tick(&record)?;
-        ticks.push(tick);
-    }
+/// `try_init_engine_with_schema` creates an engine from tick data laid
+/// out as `schema` describes, once it passes `validation`, returning the
+/// engine and the data-quality report.
+pub fn try_init_engine_with_schema<P: AsRef<Path>>(path: &P, schema: &CsvSchema, validation: &Validation, cash: i64) -> Result<(Engine, DataQualityReport), LoadError> {
+    let (prices, report) = loader::load_ticks(path, schema, validation)?;
+    let mut engine = new_engine(prices, vec![], cash);
+    engine.data_quality = Some(report.clone());
+    Ok((engine, report))
+}
 
-    Ok(TS { ticks })
+/// `init_bar_engine` creates an engine driven by OHLCV bars. Pass in a
+/// `path` to the bar data, with Date, Time, Asset, Open, High, Low, Close
+/// and Volume columns, and a starting value for `cash`.
+pub fn init_bar_engine<P: AsRef<Path>>(path: &P, cash: i64) -> Engine {
+    try_init_bar_engine(path, cash).expect("could not load bars")
 }
 
-/// `init_engine` is the main way to create a new engine. Pass in a `path`
-/// to the tick data and a starting value for `cash`.
-pub fn init_engine<P: AsRef<Path>>(path: &P, cash: i64) -> Engine {
-    let prices: TS = init_prices(path).expect("could not load prices");
-    let t1 = prices.ticks[0].timestamp;
+/// `try_init_bar_engine` is `init_bar_engine`, returning a `LoadError`
+/// instead of panicking.
+pub fn try_init_bar_engine<P: AsRef<Path>>(path: &P, cash: i64) -> Result<Engine, LoadError> {
+    let bars = loader::load_bars(path)?;
+    Ok(bar_engine(bars, cash))
+}
+
+/// `bar_engine` creates an engine driven by `bars`, e.g. ones resampled
+/// from ticks with `TS::resample`, and a starting value for `cash`.
+pub fn bar_engine(bars: Vec<Bar>, cash: i64) -> Engine {
+    let prices = TS { ticks: bars.iter().map(Bar::to_tick).collect() };
+    new_engine(prices, bars, cash)
+}
+
+fn new_engine(prices: TS, bars: Vec<Bar>, cash: i64) -> Engine {
+    let t1 = prices.ticks.first().map(|t| t.timestamp).unwrap_or_default();
     Engine {
         acct: init_acct(cash),
         time: t1,
-        prices: prices,
+        prices,
         index: 0,
         signals: vec![],
         indicators: HashMap::new(),
         last_price: HashMap::new(),
         mode: Mode::Backtest,
+        max_fill_lots: None,
+        commissions: Commissions::default(),
+        slippage: Arc::new(NoSlippage),
+        execution: ExecutionTiming::default(),
+        last_marked: None,
+        equity_curve: vec![],
+        margin_call_policy: MarginCallPolicy::default(),
+        margin_calls: vec![],
+        financing: Financing::default(),
+        last_accrual: None,
+        bars,
+        bar_fill: BarFill::default(),
+        data_quality: None,
     }
 }
\ No newline at end of file
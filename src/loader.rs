@@ -0,0 +1,547 @@
+use anyhow::anyhow;
+use chrono::prelude::*;
+use chrono::LocalResult;
+use chrono_tz::Tz;
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+
+use crate::bar::Bar;
+use crate::validation::{DataQualityReport, Validation, ValidationError};
+use crate::{Tick, TS};
+
+/// `EpochUnit` is the unit of an epoch timestamp column.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EpochUnit {
+    Seconds,
+    Millis,
+    Nanos,
+}
+
+/// `AmbiguousTime` decides which instant a local time that happens twice,
+/// when clocks go back, is taken to be: the `Earliest` (still on summer
+/// time), the `Latest`, or an `Error`.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AmbiguousTime {
+    #[default]
+    Earliest,
+    Latest,
+    Error,
+}
+
+/// `NonexistentTime` decides what happens to a local time skipped when
+/// clocks go forward. `ShiftForward` reads it with the offset from before
+/// the change, so 02:30 becomes 03:30 after a one hour jump.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonexistentTime {
+    #[default]
+    ShiftForward,
+    Error,
+}
+
+/// `TimestampColumns` says where each tick's time comes from: separate
+/// date and time columns, one combined column, or an epoch column. Formats
+/// use chrono's `strftime` syntax. A combined format with an offset (`%z`)
+/// is converted to UTC; otherwise times are local to the schema's
+/// `timezone`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum TimestampColumns {
+    Split {
+        date_column: String,
+        date_format: String,
+        time_column: String,
+        time_format: String,
+    },
+    Combined {
+        column: String,
+        format: String,
+    },
+    Epoch {
+        column: String,
+        unit: EpochUnit,
+    },
+}
+
+/// `CsvSchema` describes the layout of a CSV tick file: its `delimiter`,
+/// where to find the timestamp, and the names of the bid, ask and (if
+/// there is one) asset columns. Files without an asset column load every
+/// tick as `default_asset`. Dates and times without an offset are read in
+/// `timezone`, an IANA name such as "America/Chicago" (UTC if `None`), with
+/// DST transitions handled by `ambiguous` and `nonexistent`. The default
+/// schema reads the
+/// `Date,Time,Asset,Bid,Ask` files `init_engine` always has.
+/// ```
+/// use rsbacktester::loader::{CsvSchema, EpochUnit, TimestampColumns};
+///
+/// let schema = CsvSchema {
+///     delimiter: ';',
+///     timestamp: TimestampColumns::Epoch{column: "ts".to_string(), unit: EpochUnit::Millis},
+///     asset_column: None,
+///     default_asset: Some("EURUSD".to_string()),
+///     ..CsvSchema::default()
+/// };
+/// let ts = schema.read("ts;Bid;Ask\n1577916000000;1.1;1.2\n".as_bytes()).unwrap();
+/// assert!(ts.ticks[0].asset == "EURUSD");
+/// ```
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CsvSchema {
+    pub delimiter: char,
+    pub timestamp: TimestampColumns,
+    pub asset_column: Option<String>,
+    pub default_asset: Option<String>,
+    pub bid_column: String,
+    pub ask_column: String,
+    pub timezone: Option<String>,
+    pub ambiguous: AmbiguousTime,
+    pub nonexistent: NonexistentTime,
+}
+
+impl Default for CsvSchema {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            timestamp: TimestampColumns::Split {
+                date_column: "Date".to_string(),
+                date_format: "%Y/%m/%d".to_string(),
+                time_column: "Time".to_string(),
+                time_format: "%H:%M:%S%.f".to_string(),
+            },
+            asset_column: Some("Asset".to_string()),
+            default_asset: None,
+            bid_column: "Bid".to_string(),
+            ask_column: "Ask".to_string(),
+            timezone: None,
+            ambiguous: AmbiguousTime::default(),
+            nonexistent: NonexistentTime::default(),
+        }
+    }
+}
+
+/// `LoadError` is why market data could not be loaded: the file could not
+/// be read (`Io`), a value could not be parsed (`Parse`, with the 1-based
+/// row, counting the header, and the column if known), the schema does not
+/// fit the file (`Schema`), there were no ticks (`Empty`), the data failed
+/// validation (`Validation`), or one of several files failed to load
+/// (`File`).
+#[derive(Debug)]
+pub enum LoadError {
+    Io(std::io::Error),
+    Parse { row: usize, column: Option<String>, message: String },
+    Schema(String),
+    Empty,
+    Validation(ValidationError),
+    File { path: PathBuf, error: Box<LoadError> },
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Io(e) => write!(f, "Could not read data: {}", e),
+            LoadError::Parse { row, column: Some(column), message } => write!(f, "Row {} column {:?}: {}", row, column, message),
+            LoadError::Parse { row, column: None, message } => write!(f, "Row {}: {}", row, message),
+            LoadError::Schema(message) => write!(f, "{}", message),
+            LoadError::Empty => write!(f, "No ticks in data"),
+            LoadError::Validation(e) => write!(f, "Data failed validation: {}", e),
+            LoadError::File { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoadError::Io(e) => Some(e),
+            LoadError::Validation(e) => Some(e),
+            LoadError::File { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for LoadError {
+    fn from(e: std::io::Error) -> Self {
+        LoadError::Io(e)
+    }
+}
+
+impl From<ValidationError> for LoadError {
+    fn from(e: ValidationError) -> Self {
+        LoadError::Validation(e)
+    }
+}
+
+impl From<csv::Error> for LoadError {
+    fn from(e: csv::Error) -> Self {
+        let row = e.position().map(|p| p.line() as usize).unwrap_or_default();
+        let message = e.to_string();
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => LoadError::Io(e),
+            _ => LoadError::Parse { row, column: None, message },
+        }
+    }
+}
+
+/// `load_ticks` reads the file at `path` laid out as `schema` describes,
+/// checks it has ticks, and validates them, returning the ticks and a
+/// data-quality report.
+pub fn load_ticks<P: AsRef<Path>>(path: &P, schema: &CsvSchema, validation: &Validation) -> Result<(TS, DataQualityReport), LoadError> {
+    let mut ts = schema.load(path)?;
+    if ts.ticks.is_empty() {
+        return Err(LoadError::Empty);
+    }
+    let report = ts.validate(validation)?;
+    Ok((ts, report))
+}
+
+#[derive(Debug, Deserialize)]
+struct BarRecord {
+    #[serde(rename = "Date")]
+    pub date: String,
+    #[serde(rename = "Time")]
+    pub time: String,
+    #[serde(rename = "Asset")]
+    pub asset: String,
+    #[serde(rename = "Open")]
+    pub open: String,
+    #[serde(rename = "High")]
+    pub high: String,
+    #[serde(rename = "Low")]
+    pub low: String,
+    #[serde(rename = "Close")]
+    pub close: String,
+    #[serde(rename = "Volume")]
+    pub volume: String,
+}
+
+/// `load_bars` reads OHLCV bars from the file at `path`, with Date, Time,
+/// Asset, Open, High, Low, Close and Volume columns, dated as in the default
+/// `CsvSchema`, and checks there are some.
+pub fn load_bars<P: AsRef<Path>>(path: &P) -> Result<Vec<Bar>, LoadError> {
+    let schema = CsvSchema::default();
+    let mut rdr = csv::Reader::from_reader(std::fs::File::open(path)?);
+    let mut bars = vec![];
+    for (i, result) in rdr.deserialize().enumerate() {
+        let record: BarRecord = result?;
+        // the header is row 1
+        bars.push(record_to_bar(&schema, &record, i + 2)?);
+    }
+    if bars.is_empty() {
+        return Err(LoadError::Empty);
+    }
+    Ok(bars)
+}
+
+fn record_to_bar(schema: &CsvSchema, r: &BarRecord, row: usize) -> Result<Bar, LoadError> {
+    let error = |column: &str, message: String| LoadError::Parse { row, column: Some(column.to_string()), message };
+    let price = |column: &str, value: &str| {
+        Decimal::from_str(value).map_err(|e| error(column, format!("could not parse {:?} as a number: {}", value, e)))
+    };
+    Ok(Bar {
+        timestamp: schema.parse_timestamp(&r.date, Some(&r.time), None).map_err(|e| error("Date", e.to_string()))?,
+        asset: r.asset.clone(),
+        open: price("Open", &r.open)?,
+        high: price("High", &r.high)?,
+        low: price("Low", &r.low)?,
+        close: price("Close", &r.close)?,
+        volume: price("Volume", &r.volume)?,
+    })
+}
+
+impl CsvSchema {
+    /// `load` reads the ticks in the file at `path`.
+    pub fn load<P: AsRef<Path>>(&self, path: &P) -> Result<TS, LoadError> {
+        self.read(std::fs::File::open(path)?)
+    }
+
+    /// `read` reads ticks from CSV data with a header row.
+    pub fn read<R: std::io::Read>(&self, reader: R) -> Result<TS, LoadError> {
+        let ticks = self.reader(reader)?.collect::<Result<_, _>>()?;
+        Ok(TS { ticks })
+    }
+
+    /// `reader` reads the header row of CSV data and checks it against the
+    /// schema, returning a `TickReader` over the rest.
+    pub fn reader<R: std::io::Read>(&self, reader: R) -> Result<TickReader<'_, R>, LoadError> {
+        if !self.delimiter.is_ascii() {
+            return Err(LoadError::Schema(format!("Delimiter {:?} is not a single byte", self.delimiter)));
+        }
+        let tz = match &self.timezone {
+            Some(name) => Some(name.parse::<Tz>().map_err(|_| LoadError::Schema(format!("Unknown timezone {:?}", name)))?),
+            None => None,
+        };
+        let mut rdr = csv::ReaderBuilder::new()
+            .delimiter(self.delimiter as u8)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let headers = rdr.headers()?.clone();
+        let column = |name: &str| {
+            headers.iter().position(|h| h == name).ok_or_else(|| LoadError::Schema(format!("No column named {:?}", name)))
+        };
+        let timestamp = match &self.timestamp {
+            TimestampColumns::Split { date_column, time_column, .. } => (column(date_column)?, Some(column(time_column)?)),
+            TimestampColumns::Combined { column: c, .. } | TimestampColumns::Epoch { column: c, .. } => (column(c)?, None),
+        };
+        let asset = match (&self.asset_column, &self.default_asset) {
+            (Some(c), _) => Ok(column(c)?),
+            (None, Some(a)) => Err(a.clone()),
+            (None, None) => return Err(LoadError::Schema("Schema needs an asset_column or a default_asset".to_string())),
+        };
+        let bid = column(&self.bid_column)?;
+        let ask = column(&self.ask_column)?;
+        Ok(TickReader { schema: self, rdr, headers, tz, timestamp, asset, bid, ask, row: 1 })
+    }
+
+    fn parse_timestamp(&self, value: &str, time: Option<&str>, tz: Option<Tz>) -> anyhow::Result<DateTime<Utc>> {
+        match &self.timestamp {
+            TimestampColumns::Split { date_format, time_format, .. } => {
+                let d = NaiveDate::parse_from_str(value, date_format)?;
+                let t = NaiveTime::parse_from_str(time.unwrap_or_default(), time_format)?;
+                self.to_utc(NaiveDateTime::new(d, t), tz)
+            }
+            TimestampColumns::Combined { format, .. } => match DateTime::parse_from_str(value, format) {
+                Ok(dt) => Ok(dt.with_timezone(&Utc)),
+                Err(_) => self.to_utc(NaiveDateTime::parse_from_str(value, format)?, tz),
+            },
+            TimestampColumns::Epoch { unit, .. } => {
+                let scale = match unit {
+                    EpochUnit::Seconds => Decimal::new(1_000_000_000, 0),
+                    EpochUnit::Millis => Decimal::new(1_000_000, 0),
+                    EpochUnit::Nanos => Decimal::new(1, 0),
+                };
+                let nanos = (Decimal::from_str(value)? * scale).trunc().to_i64()
+                    .ok_or_else(|| anyhow!("Epoch time {} is out of range", value))?;
+                Ok(Utc.timestamp_nanos(nanos))
+            }
+        }
+    }
+
+    /// `to_utc` reads `local` as a time in `tz`, or as UTC without one.
+    fn to_utc(&self, local: NaiveDateTime, tz: Option<Tz>) -> anyhow::Result<DateTime<Utc>> {
+        let tz = match tz {
+            Some(tz) => tz,
+            None => return Ok(Utc.from_utc_datetime(&local)),
+        };
+        match tz.from_local_datetime(&local) {
+            LocalResult::Single(dt) => Ok(dt.with_timezone(&Utc)),
+            LocalResult::Ambiguous(earliest, latest) => match self.ambiguous {
+                AmbiguousTime::Earliest => Ok(earliest.with_timezone(&Utc)),
+                AmbiguousTime::Latest => Ok(latest.with_timezone(&Utc)),
+                AmbiguousTime::Error => Err(anyhow!("{} is ambiguous in {}", local, tz)),
+            },
+            LocalResult::None => match self.nonexistent {
+                NonexistentTime::ShiftForward => {
+                    let before = tz.from_local_datetime(&(local - chrono::Duration::days(1))).earliest()
+                        .ok_or_else(|| anyhow!("{} does not exist in {}", local, tz))?;
+                    let offset = before.offset().fix();
+                    Ok(Utc.from_utc_datetime(&(local - offset)))
+                }
+                NonexistentTime::Error => Err(anyhow!("{} does not exist in {}", local, tz)),
+            },
+        }
+    }
+}
+
+/// `TickReader` reads ticks one row at a time from CSV data laid out as its
+/// `CsvSchema` describes. Make one with `CsvSchema::reader`.
+pub struct TickReader<'a, R> {
+    schema: &'a CsvSchema,
+    rdr: csv::Reader<R>,
+    headers: csv::StringRecord,
+    tz: Option<Tz>,
+    timestamp: (usize, Option<usize>),
+    // the asset column, or the asset every tick is for
+    asset: Result<usize, String>,
+    bid: usize,
+    ask: usize,
+    row: usize,
+}
+
+impl<'a, R: std::io::Read> TickReader<'a, R> {
+    fn parse(&self, record: &csv::StringRecord) -> Result<Tick, LoadError> {
+        // the header is row 1
+        let row = record.position().map(|p| p.line() as usize).unwrap_or(self.row);
+        let error = |c: usize, message: String| LoadError::Parse { row, column: Some(self.headers[c].to_string()), message };
+        let field = |c: usize| record.get(c).ok_or_else(|| error(c, "missing".to_string()));
+        let parse_price = |c: usize| -> Result<Decimal, LoadError> {
+            let value = field(c)?;
+            Decimal::from_str(value).map_err(|e| error(c, format!("could not parse {:?} as a price: {}", value, e)))
+        };
+        let time = match self.timestamp {
+            (c, Some(t)) => self.schema.parse_timestamp(field(c)?, Some(field(t)?), self.tz),
+            (c, None) => self.schema.parse_timestamp(field(c)?, None, self.tz),
+        }.map_err(|e| error(self.timestamp.0, e.to_string()))?;
+        Ok(Tick {
+            timestamp: time,
+            asset: match &self.asset {
+                Ok(c) => field(*c)?.to_string(),
+                Err(a) => a.clone(),
+            },
+            bid: parse_price(self.bid)?,
+            ask: parse_price(self.ask)?,
+        })
+    }
+}
+
+impl<'a, R: std::io::Read> Iterator for TickReader<'a, R> {
+    type Item = Result<Tick, LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = csv::StringRecord::new();
+        self.row += 1;
+        match self.rdr.read_record(&mut record) {
+            Ok(true) => Some(self.parse(&record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e.into())),
+        }
+    }
+}
+
+/// `TickMerge` merges streams of ticks, each in time order, into one
+/// stream in time order, holding only the next tick of each. Ticks at the
+/// same time come out in the order their streams were added. The merge
+/// stops at the first error.
+pub struct TickMerge<I> {
+    streams: Vec<Option<I>>,
+    heads: Vec<Option<Tick>>,
+    heap: BinaryHeap<Reverse<(DateTime<Utc>, usize)>>,
+    error: Option<LoadError>,
+}
+
+impl<I: Iterator<Item = Result<Tick, LoadError>>> TickMerge<I> {
+    pub fn new() -> Self {
+        Self { streams: vec![], heads: vec![], heap: BinaryHeap::new(), error: None }
+    }
+
+    /// `add` adds `stream` to the merge. Its first tick must not be earlier
+    /// than the last tick the merge gave out.
+    pub fn add(&mut self, mut stream: I) -> Result<(), LoadError> {
+        if let Some(tick) = stream.next().transpose()? {
+            self.heap.push(Reverse((tick.timestamp, self.streams.len())));
+            self.heads.push(Some(tick));
+            self.streams.push(Some(stream));
+        }
+        Ok(())
+    }
+
+    /// `peek_time` is the time of the next tick, if there is one.
+    pub fn peek_time(&self) -> Option<DateTime<Utc>> {
+        self.heap.peek().map(|Reverse((time, _))| *time)
+    }
+}
+
+impl<I: Iterator<Item = Result<Tick, LoadError>>> Default for TickMerge<I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: Iterator<Item = Result<Tick, LoadError>>> Iterator for TickMerge<I> {
+    type Item = Result<Tick, LoadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(e) = self.error.take() {
+            return Some(Err(e));
+        }
+        let Reverse((_, i)) = self.heap.pop()?;
+        let tick = self.heads[i].take();
+        match self.streams[i].as_mut().and_then(|s| s.next()) {
+            Some(Ok(next)) => {
+                self.heap.push(Reverse((next.timestamp, i)));
+                self.heads[i] = Some(next);
+            }
+            Some(Err(e)) => {
+                self.heap.clear();
+                self.error = Some(e);
+            }
+            // an exhausted stream is dropped, closing its file
+            None => self.streams[i] = None,
+        }
+        tick.map(Ok)
+    }
+}
+
+/// `data_files` lists the files `pattern` names: every CSV file in it if it
+/// is a directory, otherwise every file matching it as a glob such as
+/// "archive/*/AAPL_*.csv". The files are sorted by path.
+pub fn data_files<P: AsRef<Path>>(pattern: &P) -> Result<Vec<PathBuf>, LoadError> {
+    let pattern = pattern.as_ref();
+    let not_found = |message: String| LoadError::Io(std::io::Error::new(std::io::ErrorKind::NotFound, message));
+    let mut files = if pattern.is_dir() {
+        let mut files = vec![];
+        for entry in std::fs::read_dir(pattern)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e.eq_ignore_ascii_case("csv")) {
+                files.push(path);
+            }
+        }
+        files
+    } else {
+        let glob = pattern.to_str().ok_or_else(|| LoadError::Schema(format!("{} is not valid UTF-8", pattern.display())))?;
+        let paths = glob::glob(glob).map_err(|e| LoadError::Schema(format!("Bad pattern {:?}: {}", glob, e)))?;
+        let mut files = vec![];
+        for path in paths {
+            let path = path.map_err(|e| LoadError::Io(e.into()))?;
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+        files
+    };
+    if files.is_empty() {
+        return Err(not_found(format!("no data files match {}", pattern.display())));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// `load_tick_files` reads every file `data_files(pattern)` lists, each
+/// laid out as `schema` describes and in time order, merges them into one
+/// series in time order, and validates it like `load_ticks`. A file is
+/// only opened once the merge reaches its first tick, so archives of one
+/// file per asset per day keep few files open at once.
+pub fn load_tick_files<P: AsRef<Path>>(pattern: &P, schema: &CsvSchema, validation: &Validation) -> Result<(TS, DataQualityReport), LoadError> {
+    let in_file = |path: &Path| {
+        let path = path.to_path_buf();
+        move |e| LoadError::File { path: path.clone(), error: Box::new(e) }
+    };
+    let open = |path: &Path| std::fs::File::open(path).map_err(LoadError::from).and_then(|f| schema.reader(f)).map_err(in_file(path));
+    // each file's first tick time, latest first so the next is at the end
+    let mut pending = vec![];
+    for path in data_files(pattern)? {
+        let first = open(&path)?.next().transpose().map_err(in_file(&path))?;
+        if let Some(tick) = first {
+            pending.push((tick.timestamp, path));
+        }
+    }
+    pending.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+
+    let mut merge = TickMerge::new();
+    let mut ticks = vec![];
+    loop {
+        while let Some((first, path)) = pending.last() {
+            if merge.peek_time().is_some_and(|next| next < *first) {
+                break;
+            }
+            let wrap = in_file(path);
+            merge.add(open(path)?.map(move |tick| tick.map_err(&wrap)))?;
+            pending.pop();
+        }
+        match merge.next() {
+            Some(tick) => ticks.push(tick?),
+            None => break,
+        }
+    }
+    let mut ts = TS { ticks };
+    if ts.ticks.is_empty() {
+        return Err(LoadError::Empty);
+    }
+    let report = ts.validate(validation)?;
+    Ok((ts, report))
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+use std::process::exit;
+
+use rsbacktester::builder::BuildError;
+use rsbacktester::runner::{self, RunConfig};
+
+const USAGE: &str = "usage: rsbacktester <data file> <run config> [--out <dir>]
+
+Runs a built-in strategy over the data file as the run config describes,
+prints a performance summary, and writes trades.csv and equity.csv to the
+output directory (the current one by default). With kind = 'tick_files'
+in the run config's [data] table, the data file may be a directory or a
+glob of tick files, which are merged in time order.
+
+Exits with 1 if the data cannot be loaded, and 2 if the arguments or the
+run config are wrong.";
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut positional = vec![];
+    let mut out = PathBuf::from(".");
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return;
+            }
+            "-o" | "--out" => match args.next() {
+                Some(dir) => out = PathBuf::from(dir),
+                None => fail(2, "--out needs a directory"),
+            },
+            _ => positional.push(arg),
+        }
+    }
+    let (data, config) = match positional.as_slice() {
+        [data, config] => (PathBuf::from(data), config.clone()),
+        _ => fail(2, USAGE),
+    };
+
+    let mut config = RunConfig::load(&config).unwrap_or_else(|e| fail(2, &e.to_string()));
+    config.engine.data.path = data;
+    let run = match config.run() {
+        Ok(run) => run,
+        Err(e @ BuildError::Load(_)) => fail(1, &e.to_string()),
+        Err(e) => fail(2, &e.to_string()),
+    };
+    if let Some(quality) = run.engine.data_quality.as_ref().filter(|q| !q.is_clean()) {
+        eprintln!("warning: {} data issues in {} ticks", quality.issues.len(), quality.ticks);
+    }
+    print!("{}", run.summary());
+
+    if let Err(e) = std::fs::create_dir_all(&out) {
+        fail(1, &format!("could not create {}: {}", out.display(), e));
+    }
+    let write = |name: &str, f: &dyn Fn(std::fs::File) -> csv::Result<()>| {
+        let path = out.join(name);
+        if let Err(e) = std::fs::File::create(&path).map_err(csv::Error::from).and_then(f) {
+            fail(1, &format!("could not write {}: {}", path.display(), e));
+        }
+    };
+    write("trades.csv", &|f| runner::write_trades(f, &run.result.trades));
+    write("equity.csv", &|f| runner::write_equity_curve(f, &run.engine.equity_curve));
+}
+
+fn fail(code: i32, message: &str) -> ! {
+    eprintln!("{}", message);
+    exit(code)
+}
@@ -0,0 +1,91 @@
+use chrono::prelude::*;
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+
+use crate::account::OrderId;
+
+/// `MarginRequirement` holds the `initial` margin needed to open a
+/// position and the `maintenance` margin needed to keep it, both as
+/// fractions of the position's notional value.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginRequirement {
+    pub initial: Decimal,
+    pub maintenance: Decimal,
+}
+
+/// `ShortProceeds` decides what happens to the cash raised by a short sale.
+/// With `Credit` it is added to `Account::cash` and can be spent. With
+/// `Collateral` it is held in `Account::short_collateral` against the short
+/// and released as the short is covered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ShortProceeds {
+    Credit,
+    Collateral,
+}
+
+/// `MarginModel` holds the margin requirement for each asset (a `default`
+/// and any per-asset overrides) and how short sale proceeds are treated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginModel {
+    pub default: MarginRequirement,
+    pub per_asset: HashMap<String, MarginRequirement>,
+    pub short_proceeds: ShortProceeds,
+}
+
+impl Default for MarginModel {
+    fn default() -> Self {
+        Self::cash()
+    }
+}
+
+impl MarginModel {
+    /// `cash` is an unleveraged account: every position, long or short,
+    /// needs its full notional value in equity.
+    pub fn cash() -> Self {
+        Self::new(Decimal::new(1, 0), Decimal::new(1, 0))
+    }
+
+    /// `new` uses the same `initial` and `maintenance` rates for every asset.
+    pub fn new(initial: Decimal, maintenance: Decimal) -> Self {
+        Self {
+            default: MarginRequirement { initial, maintenance },
+            per_asset: HashMap::new(),
+            short_proceeds: ShortProceeds::Credit,
+        }
+    }
+
+    pub fn requirement(&self, asset: &str) -> &MarginRequirement {
+        self.per_asset.get(asset).unwrap_or(&self.default)
+    }
+}
+
+/// `MarginCallPolicy` decides what the engine does when equity falls below
+/// the maintenance margin requirement. Every policy records a `MarginCall`
+/// and tells the strategy. `LiquidateLargestLoss` also closes positions,
+/// biggest unrealized loss first, until the requirement would be met, and
+/// `LiquidateAll` closes every position. Liquidation orders are market
+/// orders filled on the next tick.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum MarginCallPolicy {
+    #[default]
+    Notify,
+    LiquidateLargestLoss,
+    LiquidateAll,
+}
+
+/// `MarginCall` records equity falling below the maintenance requirement,
+/// and any liquidation `orders` placed in response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarginCall {
+    pub timestamp: DateTime<Utc>,
+    pub equity: Decimal,
+    pub maintenance_requirement: Decimal,
+    pub orders: Vec<OrderId>,
+}
+
+impl MarginCall {
+    /// `shortfall` is how far equity is below the maintenance requirement.
+    pub fn shortfall(&self) -> Decimal {
+        self.maintenance_requirement - self.equity
+    }
+}
@@ -0,0 +1,127 @@
+use chrono::prelude::*;
+use rust_decimal::prelude::*;
+
+const SECONDS_PER_YEAR: f64 = 365.25 * 24. * 60. * 60.;
+
+/// `EquityPoint` is the account's total equity at `timestamp`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EquityPoint {
+    pub timestamp: DateTime<Utc>,
+    pub equity: Decimal,
+}
+
+/// `BacktestReport` holds performance statistics for an equity curve.
+/// Returns are fractions (0.1 is 10%). Annualised figures are scaled by
+/// how many equity points fall in a year, worked out from the curve's
+/// timestamps, and are `None` when the curve does not span any time or
+/// the statistic is otherwise undefined (e.g. Sharpe with no volatility).
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub start: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub starting_equity: Decimal,
+    pub final_equity: Decimal,
+    pub total_return: f64,
+    pub cagr: Option<f64>,
+    pub annualised_volatility: Option<f64>,
+    pub sharpe: Option<f64>,
+    pub sortino: Option<f64>,
+    pub calmar: Option<f64>,
+    pub max_drawdown: f64,
+    pub max_drawdown_duration: chrono::Duration,
+}
+
+impl BacktestReport {
+    /// `from_equity_curve` computes a report from `curve`, using
+    /// `risk_free_rate` (annualised) for Sharpe and Sortino.
+    pub fn from_equity_curve(curve: &[EquityPoint], risk_free_rate: f64) -> Self {
+        let equities: Vec<f64> = curve.iter().map(|p| p.equity.to_f64().unwrap_or(0.)).collect();
+        let starting_equity = curve.first().map(|p| p.equity).unwrap_or_default();
+        let final_equity = curve.last().map(|p| p.equity).unwrap_or_default();
+        let start = curve.first().map(|p| p.timestamp);
+        let end = curve.last().map(|p| p.timestamp);
+
+        let first = equities.first().copied().unwrap_or(0.);
+        let last = equities.last().copied().unwrap_or(0.);
+        let total_return = if first != 0. { last / first - 1. } else { 0. };
+
+        let years = match (start, end) {
+            (Some(s), Some(e)) if e > s => (e - s).num_milliseconds() as f64 / 1000. / SECONDS_PER_YEAR,
+            _ => 0.,
+        };
+
+        let returns: Vec<f64> = equities.windows(2)
+            .filter(|w| w[0] != 0.)
+            .map(|w| w[1] / w[0] - 1.)
+            .collect();
+
+        let (max_drawdown, max_drawdown_duration) = drawdown(curve, &equities);
+
+        let mut report = Self {
+            start,
+            end,
+            starting_equity,
+            final_equity,
+            total_return,
+            cagr: None,
+            annualised_volatility: None,
+            sharpe: None,
+            sortino: None,
+            calmar: None,
+            max_drawdown,
+            max_drawdown_duration,
+        };
+        if years <= 0. || returns.is_empty() {
+            return report;
+        }
+
+        let periods_per_year = returns.len() as f64 / years;
+        let n = returns.len() as f64;
+        let mean = returns.iter().sum::<f64>() / n;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / n;
+        let downside = returns.iter().map(|r| r.min(0.).powi(2)).sum::<f64>() / n;
+        let excess = mean * periods_per_year - risk_free_rate;
+        let volatility = variance.sqrt() * periods_per_year.sqrt();
+        let downside_volatility = downside.sqrt() * periods_per_year.sqrt();
+
+        if first > 0. && last >= 0. {
+            report.cagr = Some((last / first).powf(1. / years) - 1.);
+        }
+        report.annualised_volatility = Some(volatility);
+        report.sharpe = ratio(excess, volatility);
+        report.sortino = ratio(excess, downside_volatility);
+        report.calmar = report.cagr.and_then(|c| ratio(c, max_drawdown));
+        report
+    }
+}
+
+fn ratio(numerator: f64, denominator: f64) -> Option<f64> {
+    if denominator > 0. {
+        Some(numerator / denominator)
+    } else {
+        None
+    }
+}
+
+/// `drawdown` returns the largest peak-to-trough fall as a fraction of the
+/// peak, and the longest time spent below a previous peak.
+fn drawdown(curve: &[EquityPoint], equities: &[f64]) -> (f64, chrono::Duration) {
+    let mut max_drawdown = 0.;
+    let mut longest = chrono::Duration::zero();
+    let mut peak = f64::MIN;
+    let mut peak_time: Option<DateTime<Utc>> = None;
+    for (point, equity) in curve.iter().zip(equities) {
+        if *equity >= peak {
+            peak = *equity;
+            peak_time = Some(point.timestamp);
+            continue;
+        }
+        if peak > 0. {
+            max_drawdown = f64::max(max_drawdown, (peak - equity) / peak);
+        }
+        if let Some(t) = peak_time {
+            longest = longest.max(point.timestamp - t);
+        }
+    }
+    (max_drawdown, longest)
+}
@@ -0,0 +1,162 @@
+use chrono::prelude::*;
+use chrono::DurationRound;
+use rust_decimal::prelude::*;
+
+use crate::bar::Bar;
+use crate::{Tick, TS};
+
+/// `BarSpec` decides when a bar closes. `Time` bars cover fixed intervals
+/// aligned to the Unix epoch (so a day runs midnight to midnight UTC).
+/// `Ticks` bars close after that many ticks, `Volume` bars once that much
+/// volume has traded, and `Dollar` bars once that much value (price times
+/// volume) has traded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BarSpec {
+    Time(chrono::Duration),
+    Ticks(usize),
+    Volume(Decimal),
+    Dollar(Decimal),
+}
+
+/// `PriceSource` is the side of each tick used to build a bar.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum PriceSource {
+    Bid,
+    Ask,
+    #[default]
+    Mid,
+}
+
+impl PriceSource {
+    pub fn price(&self, tick: &Tick) -> Decimal {
+        match self {
+            PriceSource::Bid => tick.bid,
+            PriceSource::Ask => tick.ask,
+            PriceSource::Mid => (tick.bid + tick.ask) / Decimal::new(2, 0),
+        }
+    }
+}
+
+/// `ResampleError` is returned by `TS::resample` when a `BarSpec::Time`
+/// `Interval` is not positive or is too long to bucket the ticks by, or by
+/// `TS::resample_with_volume` when the number of `volumes` does not match
+/// the number of `ticks`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResampleError {
+    Interval(chrono::Duration),
+    Volumes { ticks: usize, volumes: usize },
+}
+
+impl std::fmt::Display for ResampleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResampleError::Interval(d) => write!(f, "bar interval {} is not positive or is out of range", d),
+            ResampleError::Volumes { ticks, volumes } => write!(f, "{} volumes given for {} ticks", volumes, ticks),
+        }
+    }
+}
+
+impl std::error::Error for ResampleError {}
+
+/// `Building` is a bar that has not closed yet.
+struct Building {
+    bar: Bar,
+    bucket: Option<DateTime<Utc>>,
+    ticks: usize,
+    dollars: Decimal,
+}
+
+impl TS {
+    /// `resample` aggregates ticks into bars of each asset, counting every
+    /// tick as one unit of volume. See `resample_with_volume`.
+    pub fn resample(&self, spec: &BarSpec, source: &PriceSource) -> Result<Vec<Bar>, ResampleError> {
+        let volumes = vec![Decimal::new(1, 0); self.ticks.len()];
+        self.resample_with_volume(&volumes, spec, source)
+    }
+
+    /// `resample_with_volume` aggregates ticks into bars of each asset,
+    /// where `volumes[i]` is the volume traded at `ticks[i]`. Time bars are
+    /// stamped with the end of their interval and other bars with their
+    /// last tick. Bars are returned in time order, and a bar still open at
+    /// the end of the data is included. Bad intervals and volumes are a
+    /// `ResampleError`.
+    pub fn resample_with_volume(&self, volumes: &[Decimal], spec: &BarSpec, source: &PriceSource) -> Result<Vec<Bar>, ResampleError> {
+        if volumes.len() != self.ticks.len() {
+            return Err(ResampleError::Volumes { ticks: self.ticks.len(), volumes: volumes.len() });
+        }
+        if let BarSpec::Time(d) = spec {
+            if *d <= chrono::Duration::zero() || d.num_nanoseconds().is_none() {
+                return Err(ResampleError::Interval(*d));
+            }
+        }
+        let mut open: Vec<Building> = vec![];
+        let mut bars = vec![];
+        for (tick, volume) in self.ticks.iter().zip(volumes) {
+            let price = source.price(tick);
+            let bucket = match spec {
+                BarSpec::Time(d) => Some(tick.timestamp.duration_trunc(*d).map_err(|_| ResampleError::Interval(*d))?),
+                _ => None,
+            };
+            let i = match open.iter().position(|b| b.bar.asset == tick.asset) {
+                Some(i) if open[i].bucket == bucket => i,
+                Some(i) => {
+                    bars.push(close(open.remove(i), spec));
+                    open.push(start(tick, price, bucket));
+                    open.len() - 1
+                }
+                None => {
+                    open.push(start(tick, price, bucket));
+                    open.len() - 1
+                }
+            };
+
+            let building = &mut open[i];
+            let bar = &mut building.bar;
+            bar.high = bar.high.max(price);
+            bar.low = bar.low.min(price);
+            bar.close = price;
+            bar.volume += volume;
+            bar.timestamp = tick.timestamp;
+            building.ticks += 1;
+            building.dollars += price * volume;
+
+            let full = match spec {
+                BarSpec::Time(_) => false,
+                BarSpec::Ticks(n) => building.ticks >= *n,
+                BarSpec::Volume(v) => bar.volume >= *v,
+                BarSpec::Dollar(d) => building.dollars >= *d,
+            };
+            if full {
+                bars.push(close(open.remove(i), spec));
+            }
+        }
+        bars.extend(open.into_iter().map(|b| close(b, spec)));
+        bars.sort_by_key(|b| b.timestamp);
+        Ok(bars)
+    }
+}
+
+fn start(tick: &Tick, price: Decimal, bucket: Option<DateTime<Utc>>) -> Building {
+    Building {
+        bar: Bar {
+            timestamp: tick.timestamp,
+            asset: tick.asset.clone(),
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            volume: Decimal::new(0, 0),
+        },
+        bucket,
+        ticks: 0,
+        dollars: Decimal::new(0, 0),
+    }
+}
+
+fn close(building: Building, spec: &BarSpec) -> Bar {
+    let mut bar = building.bar;
+    if let (BarSpec::Time(d), Some(bucket)) = (spec, building.bucket) {
+        bar.timestamp = bucket + *d;
+    }
+    bar
+}
@@ -0,0 +1,137 @@
+use std::io::Write;
+use std::path::Path;
+
+use crate::builder::{BuildError, EngineConfig};
+use crate::ledger::RoundTrip;
+use crate::loader::LoadError;
+use crate::report::EquityPoint;
+use crate::strategies::{self, Params};
+use crate::strategy::BacktestResult;
+use crate::Engine;
+
+/// `RunConfig` is a run of a built-in `strategy` with `params`, on an
+/// engine set up by `engine`. As a TOML file it is an `EngineConfig` with
+/// two more keys, e.g.
+/// ```toml
+/// strategy = "sma_cross"
+/// params = { fast = 5, slow = 20, lots = 2 }
+/// cash = 10000
+/// commission = { model = "per_trade", amount = "1" }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub strategy: String,
+    pub params: Params,
+    pub engine: EngineConfig,
+}
+
+impl RunConfig {
+    pub fn from_toml(contents: &str) -> Result<Self, BuildError> {
+        let invalid = |e: toml::de::Error| BuildError::Config(e.to_string());
+        let mut table: toml::value::Table = toml::from_str(contents).map_err(invalid)?;
+        let strategy = match table.remove("strategy") {
+            Some(toml::Value::String(s)) => s,
+            Some(other) => return Err(BuildError::Config(format!("strategy should be a name, not {}", other))),
+            None => return Err(BuildError::Config("no strategy given".to_string())),
+        };
+        let params = match table.remove("params") {
+            Some(params) => params.try_into().map_err(invalid)?,
+            None => Params::new(),
+        };
+        let engine = toml::Value::Table(table).try_into().map_err(invalid)?;
+        Ok(Self { strategy, params, engine })
+    }
+
+    /// `load` reads a run config file. Relative paths in it are taken to be
+    /// relative to the file.
+    pub fn load<P: AsRef<Path>>(path: &P) -> Result<Self, BuildError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path).map_err(|e| BuildError::Load(LoadError::Io(e)))?;
+        let mut config = Self::from_toml(&contents)?;
+        config.engine.relative_to(path.parent().unwrap_or_else(|| Path::new("")));
+        Ok(config)
+    }
+
+    /// `run` builds the engine, sets up the strategy and runs it to the end
+    /// of the data.
+    pub fn run(&self) -> Result<Run, BuildError> {
+        let mut engine = self.engine.build()?;
+        let mut strategy = strategies::build(&self.strategy, &self.params, &mut engine).map_err(BuildError::Config)?;
+        let result = engine.run(&mut strategy);
+        Ok(Run { strategy: self.strategy.clone(), engine, result })
+    }
+}
+
+/// `Run` is a finished run: the `engine` as it was left, and its `result`.
+#[derive(Debug, Clone)]
+pub struct Run {
+    pub strategy: String,
+    pub engine: Engine,
+    pub result: BacktestResult,
+}
+
+impl Run {
+    /// `summary` is a plain text table of the run's performance.
+    pub fn summary(&self) -> String {
+        let r = &self.result;
+        let report = &r.report;
+        let stats = &r.trade_stats;
+        let percent = |v: f64| format!("{:.2}%", v * 100.);
+        let ratio = |v: Option<f64>| v.map(|v| format!("{:.2}", v)).unwrap_or_else(|| "n/a".to_string());
+        let time = |t: Option<chrono::DateTime<chrono::Utc>>| t.map(|t| t.to_string()).unwrap_or_else(|| "n/a".to_string());
+        let rows = vec![
+            ("Strategy", self.strategy.clone()),
+            ("Start", time(report.start)),
+            ("End", time(report.end)),
+            ("Ticks", r.ticks_processed.to_string()),
+            ("Starting equity", r.starting_equity.to_string()),
+            ("Final equity", r.final_equity.to_string()),
+            ("Total return", percent(report.total_return)),
+            ("CAGR", report.cagr.map(percent).unwrap_or_else(|| "n/a".to_string())),
+            ("Volatility", report.annualised_volatility.map(percent).unwrap_or_else(|| "n/a".to_string())),
+            ("Sharpe", ratio(report.sharpe)),
+            ("Sortino", ratio(report.sortino)),
+            ("Calmar", ratio(report.calmar)),
+            ("Max drawdown", percent(report.max_drawdown)),
+            ("Fills", r.fills.len().to_string()),
+            ("Trades", format!("{} ({} won, {} lost)", stats.trades, stats.wins, stats.losses)),
+            ("Win rate", stats.win_rate.map(percent).unwrap_or_else(|| "n/a".to_string())),
+            ("Profit factor", ratio(stats.profit_factor)),
+            ("Fees", self.engine.acct.fees_paid.to_string()),
+        ];
+        rows.iter().map(|(name, value)| format!("{:<16}{}\n", name, value)).collect()
+    }
+}
+
+/// `write_trades` writes `trades` as CSV, one round trip per row.
+pub fn write_trades<W: Write>(writer: W, trades: &[RoundTrip]) -> csv::Result<()> {
+    let mut w = csv::Writer::from_writer(writer);
+    w.write_record(["asset", "lots", "entry_time", "exit_time", "entry_price", "exit_price", "fees", "realized_pnl", "mae", "mfe"])?;
+    for t in trades {
+        w.write_record(&[
+            t.asset.clone(),
+            t.lots.to_string(),
+            t.entry_time.to_rfc3339(),
+            t.exit_time.to_rfc3339(),
+            t.entry_price.to_string(),
+            t.exit_price.to_string(),
+            t.fees.to_string(),
+            t.realized_pnl.to_string(),
+            t.mae.to_string(),
+            t.mfe.to_string(),
+        ])?;
+    }
+    w.flush()?;
+    Ok(())
+}
+
+/// `write_equity_curve` writes `curve` as CSV, one point per row.
+pub fn write_equity_curve<W: Write>(writer: W, curve: &[EquityPoint]) -> csv::Result<()> {
+    let mut w = csv::Writer::from_writer(writer);
+    w.write_record(["timestamp", "equity"])?;
+    for point in curve {
+        w.write_record(&[point.timestamp.to_rfc3339(), point.equity.to_string()])?;
+    }
+    w.flush()?;
+    Ok(())
+}
@@ -0,0 +1,92 @@
+use hashbrown::HashMap;
+use rust_decimal::prelude::*;
+use std::fmt::Debug;
+
+use crate::indicators::Indicator;
+use crate::Tick;
+
+/// `SlippageModel` works out how far a fill of `lots` against `tick` moves
+/// away from the touch. The result is a non-negative price amount: the
+/// engine adds it to the ask for buys and takes it off the bid for sells.
+/// `indicators` are the engine's registered indicators, for models that
+/// scale with something like volatility.
+pub trait SlippageModel: Debug + Send + Sync {
+    fn slippage(&self, tick: &Tick, lots: isize, indicators: &HashMap<String, Indicator>) -> Decimal;
+}
+
+/// `NoSlippage` fills at the touch, and is what an `Engine` starts with.
+#[derive(Debug, Clone)]
+pub struct NoSlippage;
+
+impl SlippageModel for NoSlippage {
+    fn slippage(&self, _tick: &Tick, _lots: isize, _indicators: &HashMap<String, Indicator>) -> Decimal {
+        Decimal::new(0, 0)
+    }
+}
+
+/// `FixedTicks` slips every fill by `ticks` increments of `tick_size`.
+#[derive(Debug, Clone)]
+pub struct FixedTicks {
+    pub ticks: u32,
+    pub tick_size: Decimal,
+}
+
+impl SlippageModel for FixedTicks {
+    fn slippage(&self, _tick: &Tick, _lots: isize, _indicators: &HashMap<String, Indicator>) -> Decimal {
+        self.tick_size * Decimal::from(self.ticks)
+    }
+}
+
+/// `SpreadFraction` slips every fill by a fraction of the tick's bid/ask
+/// spread, so `SpreadFraction(Decimal::new(5, 1))` pays half the spread again.
+#[derive(Debug, Clone)]
+pub struct SpreadFraction(pub Decimal);
+
+impl SlippageModel for SpreadFraction {
+    fn slippage(&self, tick: &Tick, _lots: isize, _indicators: &HashMap<String, Indicator>) -> Decimal {
+        self.0 * (tick.ask - tick.bid).abs()
+    }
+}
+
+/// `VolatilityScaled` slips every fill by `multiplier` times the current
+/// value of the indicator named `indicator`, typically a
+/// `StandardDeviation` of price. No slippage is applied until the
+/// indicator has a value.
+#[derive(Debug, Clone)]
+pub struct VolatilityScaled {
+    pub indicator: String,
+    pub multiplier: Decimal,
+}
+
+impl SlippageModel for VolatilityScaled {
+    fn slippage(&self, _tick: &Tick, _lots: isize, indicators: &HashMap<String, Indicator>) -> Decimal {
+        indicators.get(&self.indicator)
+            .and_then(|i| i.value())
+            .filter(|v| v.is_finite())
+            .and_then(Decimal::from_f64)
+            .map(|v| (v * self.multiplier).abs())
+            .unwrap_or_default()
+    }
+}
+
+/// `SquareRootImpact` models market impact growing with the square root of
+/// order size: `price * coefficient * volatility * sqrt(lots / volume)`,
+/// where `volatility` is a fractional volatility (e.g. 0.02 for 2% daily)
+/// and `volume` is the typical volume over the same period.
+#[derive(Debug, Clone)]
+pub struct SquareRootImpact {
+    pub coefficient: f64,
+    pub volatility: f64,
+    pub volume: f64,
+}
+
+impl SlippageModel for SquareRootImpact {
+    fn slippage(&self, tick: &Tick, lots: isize, _indicators: &HashMap<String, Indicator>) -> Decimal {
+        if self.volume <= 0. {
+            return Decimal::new(0, 0);
+        }
+        let mid = ((tick.ask + tick.bid) / Decimal::new(2, 0)).to_f64().unwrap_or(0.);
+        let impact = mid * self.coefficient * self.volatility * (lots.abs() as f64 / self.volume).sqrt();
+        Decimal::from_f64(impact.abs()).unwrap_or_default()
+    }
+}
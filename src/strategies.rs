@@ -0,0 +1,152 @@
+use hashbrown::HashSet;
+use std::collections::BTreeMap;
+
+use crate::indicators::{Indicator, Momentum, MovingAverage};
+use crate::strategy::{Context, Strategy};
+use crate::{Engine, Tick};
+
+/// `Params` are the numeric parameters a built-in strategy is set up with.
+pub type Params = BTreeMap<String, f64>;
+
+/// `STRATEGIES` names every built-in strategy `build` knows.
+pub const STRATEGIES: &[&str] = &["buy_and_hold", "sma_cross", "momentum"];
+
+/// `BuyAndHold` buys `lots` of each asset the first time it ticks and holds
+/// them to the end.
+#[derive(Debug, Clone, Default)]
+pub struct BuyAndHold {
+    pub lots: isize,
+    bought: HashSet<String>,
+}
+
+impl Strategy for BuyAndHold {
+    fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+        if self.bought.insert(tick.asset.clone()) {
+            ctx.place_order(&tick.asset, self.lots);
+        }
+    }
+}
+
+/// `SmaCross` holds `lots` long in the ticking asset while the `fast`
+/// moving average is above the `slow` one, and `lots` short otherwise. It
+/// waits `warmup` ticks for the averages to fill before trading.
+#[derive(Debug, Clone)]
+pub struct SmaCross {
+    pub fast: String,
+    pub slow: String,
+    pub lots: isize,
+    pub warmup: usize,
+    seen: usize,
+}
+
+impl SmaCross {
+    pub fn new(fast: String, slow: String, lots: isize, warmup: usize) -> Self {
+        Self { fast, slow, lots, warmup, seen: 0 }
+    }
+}
+
+impl Strategy for SmaCross {
+    fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+        self.seen += 1;
+        if self.seen < self.warmup {
+            return;
+        }
+        if let (Some(fast), Some(slow)) = (ctx.indicator(&self.fast), ctx.indicator(&self.slow)) {
+            let target = if fast > slow { self.lots } else { -self.lots };
+            trade_to(ctx, &tick.asset, target);
+        }
+    }
+}
+
+/// `MomentumFollow` holds `lots` long in the ticking asset while the
+/// `momentum` indicator is positive, `lots` short while it is negative,
+/// and is flat otherwise. It waits `warmup` ticks before trading.
+#[derive(Debug, Clone)]
+pub struct MomentumFollow {
+    pub momentum: String,
+    pub lots: isize,
+    pub warmup: usize,
+    seen: usize,
+}
+
+impl MomentumFollow {
+    pub fn new(momentum: String, lots: isize, warmup: usize) -> Self {
+        Self { momentum, lots, warmup, seen: 0 }
+    }
+}
+
+impl Strategy for MomentumFollow {
+    fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+        self.seen += 1;
+        if self.seen < self.warmup {
+            return;
+        }
+        if let Some(momentum) = ctx.indicator(&self.momentum) {
+            let target = if momentum > 0. {
+                self.lots
+            } else if momentum < 0. {
+                -self.lots
+            } else {
+                0
+            };
+            trade_to(ctx, &tick.asset, target);
+        }
+    }
+}
+
+/// `trade_to` orders whatever takes the position in `asset` to `target`
+/// lots, counting fills not yet applied, unless an order for it is already
+/// working.
+fn trade_to(ctx: &mut Context, asset: &str, target: isize) {
+    let acct = &ctx.engine().acct;
+    let pending: isize = acct.pending_fills.iter().filter(|f| f.asset == asset).map(|f| f.lots).sum();
+    let lots = ctx.lots(asset) + pending;
+    let working = acct.orders.iter().any(|o| o.asset == asset && o.is_open());
+    if lots != target && !working {
+        ctx.place_order(asset, target - lots);
+    }
+}
+
+/// `build` sets up the built-in strategy called `name` with `params`,
+/// registering any indicators it needs on `engine`. Parameters left out
+/// take their defaults: `lots` is 1 everywhere, `sma_cross` has `fast` 10
+/// and `slow` 30, and `momentum` has `length` 20. Built-in strategies trade
+/// whichever asset ticks, and their indicators see every tick, so they are
+/// meant for single-asset data.
+pub fn build(name: &str, params: &Params, engine: &mut Engine) -> Result<Box<dyn Strategy>, String> {
+    let known: &[&str] = match name {
+        "buy_and_hold" => &["lots"],
+        "sma_cross" => &["lots", "fast", "slow"],
+        "momentum" => &["lots", "length"],
+        _ => return Err(format!("Unknown strategy {:?}, expected one of {}", name, STRATEGIES.join(", "))),
+    };
+    if let Some(unknown) = params.keys().find(|k| !known.contains(&k.as_str())) {
+        return Err(format!("Strategy {} has no parameter {:?}", name, unknown));
+    }
+    let count = |param: &str, default: usize| -> Result<usize, String> {
+        let value = params.get(param).copied().unwrap_or(default as f64);
+        if value < 1. || value.fract() != 0. {
+            return Err(format!("Parameter {} of {} must be a whole number of at least 1, not {}", param, name, value));
+        }
+        Ok(value as usize)
+    };
+    let lots = count("lots", 1)? as isize;
+
+    Ok(match name {
+        "buy_and_hold" => Box::new(BuyAndHold { lots, ..BuyAndHold::default() }),
+        "sma_cross" => {
+            let (fast, slow) = (count("fast", 10)?, count("slow", 30)?);
+            if fast >= slow {
+                return Err(format!("sma_cross needs fast ({}) shorter than slow ({})", fast, slow));
+            }
+            engine.register_indicator("sma_cross.fast".to_string(), Indicator::MovingAverage(MovingAverage::new(fast, "price".to_string())));
+            engine.register_indicator("sma_cross.slow".to_string(), Indicator::MovingAverage(MovingAverage::new(slow, "price".to_string())));
+            Box::new(SmaCross::new("sma_cross.fast".to_string(), "sma_cross.slow".to_string(), lots, slow))
+        }
+        _ => {
+            let length = count("length", 20)?;
+            engine.register_indicator("momentum".to_string(), Indicator::Momentum(Momentum::new(length, "price".to_string())));
+            Box::new(MomentumFollow::new("momentum".to_string(), lots, length))
+        }
+    })
+}
@@ -0,0 +1,119 @@
+use chrono::prelude::*;
+use rust_decimal::prelude::*;
+
+use crate::account::{Fill, Order, OrderId, OrderType};
+use crate::ledger::{RoundTrip, TradeStats};
+use crate::margin::MarginCall;
+use crate::position::Position;
+use crate::report::BacktestReport;
+use crate::{Engine, Tick};
+
+/// `Strategy` is implemented by anything that wants to be driven by
+/// `Engine::run`. Only `on_tick` is required; the other hooks default to
+/// doing nothing.
+///
+/// `on_end` is called once, after the final tick has been fully processed,
+/// so positions and cash include its fills. Orders placed there (e.g. to flatten positions) are filled against the final tick,
+/// whatever the engine's `ExecutionTiming`.
+pub trait Strategy {
+    fn on_start(&mut self, _ctx: &mut Context) {}
+    fn on_tick(&mut self, tick: &Tick, ctx: &mut Context);
+    fn on_fill(&mut self, _fill: &Fill, _ctx: &mut Context) {}
+    fn on_margin_call(&mut self, _call: &MarginCall, _ctx: &mut Context) {}
+    fn on_end(&mut self, _ctx: &mut Context) {}
+}
+
+impl<S: Strategy + ?Sized> Strategy for Box<S> {
+    fn on_start(&mut self, ctx: &mut Context) {
+        (**self).on_start(ctx)
+    }
+    fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+        (**self).on_tick(tick, ctx)
+    }
+    fn on_fill(&mut self, fill: &Fill, ctx: &mut Context) {
+        (**self).on_fill(fill, ctx)
+    }
+    fn on_margin_call(&mut self, call: &MarginCall, ctx: &mut Context) {
+        (**self).on_margin_call(call, ctx)
+    }
+    fn on_end(&mut self, ctx: &mut Context) {
+        (**self).on_end(ctx)
+    }
+}
+
+/// `Context` is what a `Strategy` sees of the `Engine` while it is running:
+/// account state, indicator values, last prices, and a way to place orders.
+pub struct Context<'a> {
+    engine: &'a mut Engine,
+}
+
+impl<'a> Context<'a> {
+    pub fn new(engine: &'a mut Engine) -> Self {
+        Self { engine }
+    }
+
+    pub fn engine(&self) -> &Engine {
+        self.engine
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.engine.time
+    }
+
+    pub fn cash(&self) -> Decimal {
+        self.engine.acct.cash
+    }
+
+    pub fn equity(&self) -> Decimal {
+        self.engine.equity()
+    }
+
+    pub fn position(&self, asset: &str) -> Option<&Position> {
+        self.engine.acct.portfolio.get(asset)
+    }
+
+    /// `lots` is the signed number of lots currently held in `asset`, or 0.
+    pub fn lots(&self, asset: &str) -> isize {
+        self.position(asset).map(|p| p.lots).unwrap_or(0)
+    }
+
+    pub fn indicator(&self, name: &str) -> Option<f64> {
+        self.engine.indicators.get(name).and_then(|i| i.value())
+    }
+
+    pub fn last_price(&self, asset: &str) -> Option<Decimal> {
+        self.engine.last_price.get(asset).copied()
+    }
+
+    pub fn order(&self, id: OrderId) -> Option<&Order> {
+        self.engine.acct.order(id)
+    }
+
+    pub fn place_order(&mut self, asset: &str, lots: isize) -> OrderId {
+        self.engine.place_order(asset.to_string(), lots)
+    }
+
+    pub fn submit_order(&mut self, asset: &str, lots: isize, order_type: OrderType) -> OrderId {
+        self.engine.submit_order(asset.to_string(), lots, order_type)
+    }
+
+    pub fn cancel_order(&mut self, id: OrderId) -> anyhow::Result<()> {
+        self.engine.cancel_order(id)
+    }
+
+    pub fn modify_order(&mut self, id: OrderId, lots: isize, order_type: OrderType) -> anyhow::Result<()> {
+        self.engine.modify_order(id, lots, order_type)
+    }
+}
+
+/// `BacktestResult` is returned by `Engine::run` and summarises a completed run.
+#[derive(Debug, Clone)]
+pub struct BacktestResult {
+    pub starting_equity: Decimal,
+    pub final_equity: Decimal,
+    pub ticks_processed: usize,
+    pub fills: Vec<Fill>,
+    pub trades: Vec<RoundTrip>,
+    pub trade_stats: TradeStats,
+    pub report: BacktestReport,
+}
@@ -1,11 +1,43 @@
 #[cfg(test)]
+#[allow(clippy::module_inception, clippy::len_zero, clippy::identity_op, clippy::neg_multiply)]
 mod tests {
-    use crate::{indicators, indicators::Indicator, init_engine, Tick, TS, account::Account, position::Position, account::OrderState};
-    use hashbrown::HashMap;
+    use crate::{bar_engine, indicators, indicators::Indicator, init_bar_engine, init_engine, init_engine_with_schema, try_init_bar_engine, try_init_engine, try_init_engine_with_schema, Engine, ExecutionTiming, Tick, TS, account::Account, account::Fill, position::Position, account::OrderId, account::OrderState, account::OrderType};
+    use crate::bar::{Bar, BarFill};
+    use crate::builder::{BuildError, DataSource, EngineBuilder, EngineConfig};
+    use crate::commission::{CommissionModel, Percentage, PerLot, PerTrade, Tier, Tiered};
+    use crate::financing::{AccrualKind, Financing};
+    use crate::fx;
+    use crate::instrument::{AssetClass, Instrument, Instruments, TickPolicy, TradingSession};
+    use crate::ledger::{self, TradeStats};
+    use crate::loader::{self, AmbiguousTime, CsvSchema, EpochUnit, LoadError, NonexistentTime, TickMerge, TimestampColumns};
+    use crate::margin::{MarginCall, MarginCallPolicy, MarginModel, MarginRequirement, ShortProceeds};
+    use crate::report::{BacktestReport, EquityPoint};
+    use crate::resample::{BarSpec, PriceSource, ResampleError};
+    use crate::slippage::{FixedTicks, SlippageModel, SpreadFraction, SquareRootImpact, VolatilityScaled};
+    use crate::runner::{self, RunConfig};
+    use crate::strategies::{self, Params};
+    use crate::strategy::{Context, Strategy};
+    use crate::validation::{IssueKind, Validation, ValidationPolicy};
     use chrono::prelude::*;
+    use hashbrown::HashMap;
     use rust_decimal::Decimal;
     use std::path::Path;
 
+    /// `engine_with_prices` loads the test engine and swaps its ticks for one
+    /// AAPL tick per `(bid, ask)` pair, a minute apart.
+    fn engine_with_prices(prices: &[(i64, i64)]) -> Engine {
+        let mut e = init_engine(&"test_resources/ticks.csv", 10000);
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap();
+        e.prices.ticks = prices.iter().enumerate().map(|(i, (bid, ask))| Tick {
+            timestamp: start + chrono::Duration::minutes(i as i64),
+            asset: "AAPL".to_string(),
+            bid: Decimal::new(*bid, 0),
+            ask: Decimal::new(*ask, 0),
+        }).collect();
+        e.time = start;
+        e
+    }
+
     #[test]
     fn test_tick() {
         let t = Tick {
@@ -82,16 +114,16 @@ mod tests {
 
     #[test]
     fn acct_open_position() {
-        let mut acct = Account{cash: Decimal::new(10000, 0), portfolio: HashMap::new(), trades: vec![], orders: vec![]};
+        let mut acct = Account::new(Decimal::new(10000, 0));
         let pos = Position{asset: "AAPL".to_string(), lots: 1, cost_basis: Decimal::new(1000, 0)};
-        let resp = acct.position(pos);
+        let resp = acct.position(pos, &HashMap::new());
         assert!(resp.is_ok());
         assert!(acct.portfolio.len() == 1);
         assert!(acct.cash.eq(&Decimal::new(9000, 0)));
         assert!(acct.portfolio["AAPL"].lots == 1);
 
         let p2 = Position{asset: "MSFT".to_string(), lots: 1, cost_basis: Decimal::new(2000, 0)};
-        let resp = acct.position(p2);
+        let resp = acct.position(p2, &HashMap::new());
         assert!(resp.is_ok());
         assert!(acct.portfolio.len() == 2);
         assert!(acct.cash.eq(&Decimal::new(7000, 0)));
@@ -99,7 +131,7 @@ mod tests {
 
 
         let p3 = Position{asset: "AAPL".to_string(), lots: 2, cost_basis: Decimal::new(250, 0)};
-        let resp = acct.position(p3);
+        let resp = acct.position(p3, &HashMap::new());
         assert!(resp.is_ok());
         assert!(acct.portfolio.len() == 2);
         assert!(acct.cash.eq(&Decimal::new(6500, 0)));
@@ -108,7 +140,7 @@ mod tests {
 
 
         let p4 = Position{asset: "AAPL".to_string(), lots: 1, cost_basis: Decimal::new(10000, 0)};
-        let resp = acct.position(p4);
+        let resp = acct.position(p4, &HashMap::new());
         assert!(resp.is_err());
         assert!(acct.cash.eq(&Decimal::new(6500, 0)));
         assert!(acct.portfolio.get("AAPL").unwrap().lots == 3);
@@ -116,15 +148,15 @@ mod tests {
 
     #[test]
     fn acct_open_close() {
-        let mut acct = Account{cash: Decimal::new(10000, 0), portfolio: HashMap::new(), trades: vec![], orders: vec![]};
+        let mut acct = Account::new(Decimal::new(10000, 0));
         let pos = Position{asset: "AAPL".to_string(), lots: 1, cost_basis: Decimal::new(1000, 0)};
-        let resp = acct.position(pos);
+        let resp = acct.position(pos, &HashMap::new());
         assert!(resp.is_ok());
         assert!(acct.portfolio.len() == 1);
         assert!(acct.cash.eq(&Decimal::new(9000, 0)));
 
         let pos = Position{asset: "AAPL".to_string(), lots: -1, cost_basis: Decimal::new(3000, 0)};
-        let resp = acct.position(pos);
+        let resp = acct.position(pos, &HashMap::new());
         assert!(resp.is_ok());
         assert!(acct.portfolio.len() == 0);
         assert!(acct.cash.eq(&Decimal::new(12000, 0)));
@@ -159,12 +191,12 @@ mod tests {
         assert!(engine.acct.trades.len() == 2);
         assert!(engine.acct.cash == Decimal::new(10001, 0));
     }
-    
+
     #[test]
     fn total_equity() {
         let mut e = init_engine(&"test_resources/ticks.csv", 10000);
         assert!(e.equity() == Decimal::new(10000, 0));
-        let result = e.acct.position(Position{asset: "AAPL".to_string(), lots: 1, cost_basis: Decimal::new(2, 0)});
+        let result = e.acct.position(Position{asset: "AAPL".to_string(), lots: 1, cost_basis: Decimal::new(2, 0)}, &e.last_price);
         assert!(result.is_ok());
         e.step();
         assert!(e.equity() == Decimal::new(9998+0, 0));
@@ -175,7 +207,7 @@ mod tests {
         e.step();
         assert!(e.equity() == Decimal::new(9998+3, 0));
     }
-    
+
     #[test]
     fn e2e_test() {
         let mut e = init_engine(&"test_resources/ticks.csv", 10000);
@@ -212,4 +244,1386 @@ mod tests {
         assert!(e.acct.portfolio.len() == 0);
         assert!(e.acct.cash.gt(&Decimal::new(10000, 0)));
     }
+
+    struct SmaCross {
+        fills: usize,
+        started: bool,
+        ended: bool,
+    }
+
+    impl Strategy for SmaCross {
+        fn on_start(&mut self, _ctx: &mut Context) {
+            self.started = true;
+        }
+
+        fn on_tick(&mut self, _tick: &Tick, ctx: &mut Context) {
+            let (short, long) = match (ctx.indicator("short_sma"), ctx.indicator("long_sma")) {
+                (Some(s), Some(l)) => (s, l),
+                _ => return,
+            };
+            let lots = ctx.lots("AAPL");
+            if short > long && lots < 1 {
+                ctx.place_order("AAPL", 1 - lots);
+            } else if short <= long && lots > -1 {
+                ctx.place_order("AAPL", -1 - lots);
+            }
+        }
+
+        fn on_fill(&mut self, _fill: &Fill, _ctx: &mut Context) {
+            self.fills += 1;
+        }
+
+        fn on_end(&mut self, ctx: &mut Context) {
+            self.ended = true;
+            let lots = ctx.lots("AAPL");
+            if lots != 0 {
+                ctx.place_order("AAPL", -lots);
+            }
+        }
+    }
+
+    #[test]
+    fn run_strategy() {
+        let mut e = init_engine(&"test_resources/ticks.csv", 10000);
+        let long_sma = indicators::MovingAverage::new(4, "price".to_string());
+        let short_sma = indicators::MovingAverage::new(2, "price".to_string());
+        e.register_indicator("long_sma".to_string(), Indicator::MovingAverage(long_sma));
+        e.register_indicator("short_sma".to_string(), Indicator::MovingAverage(short_sma));
+
+        let mut s = SmaCross{fills: 0, started: false, ended: false};
+        let result = e.run(&mut s);
+        assert!(s.started && s.ended);
+        assert!(result.ticks_processed == e.prices.ticks.len());
+        assert!(result.fills.len() == s.fills);
+        assert!(!result.fills.is_empty());
+        assert!(e.acct.portfolio.is_empty());
+        assert!(result.starting_equity == Decimal::new(10000, 0));
+        assert!(result.final_equity == e.acct.cash);
+    }
+
+    #[test]
+    fn limit_orders_rest_until_touched() {
+        let mut e = engine_with_prices(&[(10, 11), (9, 10), (7, 8), (9, 10), (12, 13)]);
+        e.submit_order("AAPL".to_string(), 1, OrderType::Limit(Decimal::new(8, 0)));
+        e.step();
+        e.step();
+        assert!(e.acct.orders[0].state == OrderState::Pending);
+        assert!(e.acct.portfolio.is_empty());
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(8, 0));
+
+        e.submit_order("AAPL".to_string(), -1, OrderType::Limit(Decimal::new(11, 0)));
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].lots == 1);
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        assert!(e.acct.cash == Decimal::new(10004, 0));
+    }
+
+    #[test]
+    fn stop_orders_trigger_on_touch() {
+        let mut e = engine_with_prices(&[(10, 11), (12, 13), (8, 9), (5, 6)]);
+        e.submit_order("AAPL".to_string(), 1, OrderType::Stop(Decimal::new(12, 0)));
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(13, 0));
+
+        e.submit_order("AAPL".to_string(), -1, OrderType::Stop(Decimal::new(9, 0)));
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        assert!(e.acct.cash == Decimal::new(10000 - 13 + 8, 0));
+    }
+
+    #[test]
+    fn stop_limit_orders_become_limits() {
+        let mut e = engine_with_prices(&[(10, 11), (14, 15), (13, 14), (11, 12)]);
+        let order_type = OrderType::StopLimit{stop: Decimal::new(14, 0), limit: Decimal::new(12, 0)};
+        e.submit_order("AAPL".to_string(), 1, order_type);
+        e.step();
+        e.step();
+        assert!(e.acct.orders[0].order_type == OrderType::Limit(Decimal::new(12, 0)));
+        assert!(e.acct.portfolio.is_empty());
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(12, 0));
+    }
+
+    #[test]
+    fn cancel_and_modify_orders() {
+        let mut e = engine_with_prices(&[(10, 11), (9, 10), (8, 9), (7, 8)]);
+        let a = e.submit_order("AAPL".to_string(), 1, OrderType::Limit(Decimal::new(5, 0)));
+        let b = e.submit_order("AAPL".to_string(), 2, OrderType::Limit(Decimal::new(5, 0)));
+        assert!(a != b);
+        e.step();
+        assert!(e.cancel_order(a).is_ok());
+        assert!(e.acct.order(a).unwrap().state == OrderState::Cancelled);
+        assert!(e.modify_order(b, -2, OrderType::Market).is_err());
+        assert!(e.modify_order(b, 3, OrderType::Limit(Decimal::new(10, 0))).is_ok());
+        assert!(e.acct.order(b).unwrap().state == OrderState::Executed);
+        e.step();
+        assert!(e.acct.order(a).is_none());
+        assert!(e.acct.orders.is_empty());
+        assert!(e.acct.portfolio["AAPL"].lots == 3);
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(10, 0));
+        assert!(e.cancel_order(b).is_err());
+    }
+
+    #[test]
+    fn partial_fills() {
+        let mut e = engine_with_prices(&[(10, 11), (9, 10), (8, 9), (7, 8)]);
+        e.max_fill_lots = Some(2);
+        let id = e.place_order("AAPL".to_string(), 5);
+        assert!(e.acct.order(id).unwrap().state == OrderState::PartiallyFilled);
+        assert!(e.acct.order(id).unwrap().filled_lots == 2);
+        e.step();
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].lots == 4);
+        assert!(e.modify_order(id, 3, OrderType::Market).is_err());
+        assert!(e.cancel_order(id).is_ok());
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].lots == 4);
+        assert!(e.acct.orders.is_empty());
+    }
+
+    #[test]
+    fn commission_models() {
+        let price = Decimal::new(50, 0);
+        let one = Decimal::new(1, 0);
+        assert!(PerLot(Decimal::new(2, 0)).commission(-3, price, one) == Decimal::new(6, 0));
+        assert!(PerTrade(Decimal::new(5, 0)).commission(100, price, one) == Decimal::new(5, 0));
+        assert!(Percentage(Decimal::new(1, 2)).commission(2, price, one) == Decimal::new(1, 0));
+        assert!(Percentage(Decimal::new(1, 2)).commission(2, price, Decimal::new(10, 0)) == Decimal::new(10, 0));
+        let tiered = Tiered{
+            tiers: vec![Tier{min_lots: 0, per_lot: Decimal::new(10, 2)}, Tier{min_lots: 100, per_lot: Decimal::new(5, 2)}],
+            minimum: Decimal::new(1, 0),
+        };
+        assert!(tiered.commission(5, price, one) == Decimal::new(1, 0));
+        assert!(tiered.commission(50, price, one) == Decimal::new(5, 0));
+        assert!(tiered.commission(200, price, one) == Decimal::new(10, 0));
+    }
+
+    #[test]
+    fn commissions_are_charged_on_fills() {
+        let mut e = engine_with_prices(&[(10, 10), (12, 12), (12, 12)]);
+        e.set_commission_model(PerTrade(Decimal::new(1, 0)));
+        e.set_asset_commission_model("AAPL".to_string(), PerLot(Decimal::new(2, 0)));
+        e.place_order("AAPL".to_string(), 3);
+        e.step();
+        assert!(e.acct.cash == Decimal::new(10000 - 30 - 6, 0));
+        e.place_order("AAPL".to_string(), -3);
+        let fills = e.update_account_orders();
+        assert!(fills[0].fee == Decimal::new(6, 0));
+        assert!(e.acct.fees_paid == Decimal::new(12, 0));
+        assert!(e.acct.cash == Decimal::new(10000 + 6 - 12, 0));
+    }
+
+    #[test]
+    fn slippage_models() {
+        let t = Tick{timestamp: Utc::now(), asset: "AAPL".to_string(), bid: Decimal::new(99, 0), ask: Decimal::new(101, 0)};
+        let inds = HashMap::new();
+        assert!(FixedTicks{ticks: 2, tick_size: Decimal::new(25, 2)}.slippage(&t, 1, &inds) == Decimal::new(5, 1));
+        assert!(SpreadFraction(Decimal::new(5, 1)).slippage(&t, 1, &inds) == Decimal::new(1, 0));
+        let impact = SquareRootImpact{coefficient: 1., volatility: 0.02, volume: 400.};
+        assert!(impact.slippage(&t, 100, &inds) == Decimal::new(1, 0));
+        assert!(impact.slippage(&t, -400, &inds) == Decimal::new(2, 0));
+
+        let vol = VolatilityScaled{indicator: "sd".to_string(), multiplier: Decimal::new(2, 0)};
+        assert!(vol.slippage(&t, 1, &inds) == Decimal::new(0, 0));
+        let mut sd = Indicator::StandardDeviation(indicators::StandardDeviation::new(2, "price".to_string()));
+        sd.update(Some(1.));
+        sd.update(Some(3.));
+        let mut inds = HashMap::new();
+        inds.insert("sd".to_string(), sd);
+        assert!(vol.slippage(&t, 1, &inds) == Decimal::new(2, 0));
+    }
+
+    #[test]
+    fn slippage_applies_to_market_not_limit_fills() {
+        let mut e = engine_with_prices(&[(10, 11), (10, 11), (10, 11)]);
+        e.set_slippage_model(FixedTicks{ticks: 1, tick_size: Decimal::new(1, 0)});
+        e.place_order("AAPL".to_string(), 1);
+        e.submit_order("AAPL".to_string(), -1, OrderType::Limit(Decimal::new(10, 0)));
+        let fills = e.update_account_orders();
+        assert!(fills[0].price == Decimal::new(12, 0));
+        assert!(fills[1].price == Decimal::new(10, 0));
+        e.place_order("AAPL".to_string(), -1);
+        let fills = e.update_account_orders();
+        assert!(fills[0].price == Decimal::new(9, 0));
+    }
+
+    struct BuyLast {
+        lots_at_end: Option<isize>,
+        fills_after_end: usize,
+    }
+
+    impl Strategy for BuyLast {
+        fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+            if tick.bid == Decimal::new(11, 0) {
+                ctx.place_order("AAPL", 1);
+            }
+        }
+
+        fn on_fill(&mut self, _fill: &Fill, _ctx: &mut Context) {
+            if self.lots_at_end.is_some() {
+                self.fills_after_end += 1;
+            }
+        }
+
+        fn on_end(&mut self, ctx: &mut Context) {
+            self.lots_at_end = Some(ctx.lots("AAPL"));
+            ctx.place_order("AAPL", -1);
+        }
+    }
+
+    #[test]
+    fn on_end_after_last_tick() {
+        // the order placed on the second to last tick fills on the last one
+        let mut e = engine_with_prices(&[(10, 10), (11, 11), (12, 12)]);
+        e.execution = ExecutionTiming::NextTick;
+        let mut s = BuyLast{lots_at_end: None, fills_after_end: 0};
+        let result = e.run(&mut s);
+        assert!(s.lots_at_end == Some(1));
+        // only the closing order from on_end fills afterwards, at the last tick
+        assert!(s.fills_after_end == 1);
+        assert!(result.fills.len() == 2);
+        assert!(result.fills[1].price == Decimal::new(12, 0));
+        assert!(e.acct.portfolio.len() == 0);
+        assert!(e.equity_curve.last().unwrap().equity == result.final_equity);
+    }
+
+    struct BuyOnce {
+        fill: Option<Fill>,
+    }
+
+    impl Strategy for BuyOnce {
+        fn on_tick(&mut self, tick: &Tick, ctx: &mut Context) {
+            if tick.bid == Decimal::new(10, 0) && ctx.lots("AAPL") == 0 {
+                ctx.place_order("AAPL", 1);
+            }
+        }
+
+        fn on_fill(&mut self, fill: &Fill, _ctx: &mut Context) {
+            self.fill = Some(fill.clone());
+        }
+    }
+
+    #[test]
+    fn execution_timing() {
+        let prices = [(9, 9), (10, 10), (11, 11), (12, 12), (13, 13), (14, 14)];
+        let mut e = engine_with_prices(&prices);
+        e.execution = ExecutionTiming::SameTick;
+        let mut s = BuyOnce{fill: None};
+        e.run(&mut s);
+        assert!(s.fill.unwrap().price == Decimal::new(10, 0));
+
+        let mut e = engine_with_prices(&prices);
+        let mut s = BuyOnce{fill: None};
+        e.run(&mut s);
+        assert!(s.fill.unwrap().price == Decimal::new(11, 0));
+
+        let mut e = engine_with_prices(&prices);
+        e.execution = ExecutionTiming::Latency(chrono::Duration::seconds(150));
+        let mut s = BuyOnce{fill: None};
+        e.run(&mut s);
+        let fill = s.fill.unwrap();
+        assert!(fill.price == Decimal::new(13, 0));
+        assert!(fill.timestamp == e.prices.ticks[4].timestamp);
+    }
+
+    #[test]
+    fn end_settlement_keeps_fill_cap() {
+        let mut e = engine_with_prices(&[(9, 9), (10, 10), (11, 11)]);
+        e.max_fill_lots = Some(1);
+        let result = e.run(&mut BuyFirstTick{lots: 5, placed: false});
+        let fills: Vec<(usize, isize)> = result.fills.iter()
+            .map(|f| (e.prices.ticks.iter().position(|t| t.timestamp == f.timestamp).unwrap(), f.lots))
+            .collect();
+        assert!(fills == vec![(1, 1), (2, 1)]);
+        assert!(e.acct.portfolio["AAPL"].lots == 2);
+    }
+
+    struct WatchOwnOrder {
+        id: Option<OrderId>,
+        seen: Vec<(isize, Option<OrderState>)>,
+    }
+
+    impl Strategy for WatchOwnOrder {
+        fn on_tick(&mut self, _tick: &Tick, ctx: &mut Context) {
+            match self.id {
+                None => self.id = Some(ctx.place_order("AAPL", 1)),
+                Some(id) => self.seen.push((ctx.lots("AAPL"), ctx.order(id).map(|o| o.state.clone()))),
+            }
+        }
+    }
+
+    #[test]
+    fn next_tick_fill_seen_on_next_tick() {
+        let mut e = engine_with_prices(&[(9, 9), (10, 10), (11, 11)]);
+        let mut s = WatchOwnOrder{id: None, seen: vec![]};
+        let result = e.run(&mut s);
+        assert!(result.fills.len() == 1 && result.fills[0].price == Decimal::new(10, 0));
+        // the fill is applied before the strategy sees the tick it filled on
+        assert!(s.seen == vec![(1, None), (1, None)]);
+    }
+
+    #[test]
+    fn report_statistics() {
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+        let curve: Vec<EquityPoint> = [100, 110, 99, 121].iter().enumerate().map(|(i, e)| EquityPoint{
+            timestamp: start + chrono::Duration::days(i as i64 * 122),
+            equity: Decimal::new(*e, 0),
+        }).collect();
+        let r = BacktestReport::from_equity_curve(&curve, 0.);
+        assert!((r.total_return - 0.21).abs() < 1e-9);
+        assert!((r.max_drawdown - 0.1).abs() < 1e-9);
+        assert!(r.max_drawdown_duration == chrono::Duration::days(122));
+        let cagr = r.cagr.unwrap();
+        assert!(cagr > 0.2 && cagr < 0.21);
+        assert!(r.annualised_volatility.unwrap() > 0.);
+        assert!(r.sharpe.unwrap() > 0.);
+        assert!(r.sortino.unwrap() > r.sharpe.unwrap());
+        assert!((r.calmar.unwrap() - cagr / 0.1).abs() < 1e-9);
+
+        let flat = BacktestReport::from_equity_curve(&curve[..1], 0.);
+        assert!(flat.total_return == 0. && flat.cagr.is_none() && flat.sharpe.is_none());
+    }
+
+    #[test]
+    fn engine_records_equity_curve() {
+        let mut e = engine_with_prices(&[(10, 10), (12, 12), (9, 9), (15, 15)]);
+        e.execution = ExecutionTiming::SameTick;
+        let mut s = BuyOnce{fill: None};
+        let result = e.run(&mut s);
+        assert!(e.equity_curve.len() == 4);
+        assert!(e.equity_curve[3].equity == Decimal::new(10005, 0));
+        assert!((result.report.total_return - 0.0005).abs() < 1e-9);
+        assert!((result.report.max_drawdown - 3. / 10002.).abs() < 1e-9);
+        assert!(e.report().final_equity == Decimal::new(10005, 0));
+    }
+
+    fn fill(minute: i64, lots: isize, price: i64, fee: i64) -> Fill {
+        Fill{
+            order_id: OrderId(minute as u64),
+            timestamp: Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap() + chrono::Duration::minutes(minute),
+            asset: "AAPL".to_string(),
+            lots,
+            price: Decimal::new(price, 0),
+            fee: Decimal::new(fee, 0),
+        }
+    }
+
+    #[test]
+    fn round_trip_ledger() {
+        let e = engine_with_prices(&[(10, 10), (8, 8), (14, 14), (13, 13), (16, 16), (12, 12), (11, 11)]);
+        let fills = vec![
+            fill(0, 2, 10, 2),
+            fill(2, -1, 14, 1),
+            fill(3, -1, 13, 1),
+            fill(4, -2, 16, 2),
+            fill(6, 2, 11, 2),
+        ];
+        let trips = ledger::round_trips(&fills, &e.prices.ticks, &Instruments::default());
+        assert!(trips.len() == 2);
+        assert!(trips[0].lots == 2);
+        assert!(trips[0].holding_period == chrono::Duration::minutes(3));
+        assert!(trips[0].exit_price == Decimal::new(135, 1));
+        assert!(trips[0].realized_pnl == Decimal::new(4 + 3 - 4, 0));
+        assert!(trips[0].mae == Decimal::new(2, 0));
+        assert!(trips[0].mfe == Decimal::new(4, 0));
+        assert!(trips[1].lots == -2);
+        assert!(trips[1].realized_pnl == Decimal::new(10 - 4, 0));
+        assert!(trips[1].mfe == Decimal::new(5, 0));
+
+        let flip = vec![fill(0, 2, 10, 4), fill(2, -4, 14, 4), fill(6, 2, 11, 0)];
+        let trips = ledger::round_trips(&flip, &e.prices.ticks, &Instruments::default());
+        assert!(trips.len() == 2);
+        assert!(trips[0].realized_pnl == Decimal::new(8 - 4 - 2, 0));
+        assert!(trips[1].entry_price == Decimal::new(14, 0));
+        assert!(trips[1].realized_pnl == Decimal::new(6 - 2, 0));
+
+        let stats = TradeStats::from_round_trips(&trips);
+        assert!(stats.trades == 2 && stats.wins == 2 && stats.losses == 0);
+        assert!(stats.win_rate == Some(1.));
+        assert!(stats.profit_factor.is_none());
+        assert!(stats.expectancy == Some(Decimal::new(3, 0)));
+    }
+
+    #[test]
+    fn engine_fill_ledger() {
+        let mut e = engine_with_prices(&[(10, 10), (12, 12), (9, 9), (15, 15)]);
+        e.set_commission_model(PerTrade(Decimal::new(1, 0)));
+        e.place_order("AAPL".to_string(), 1);
+        e.step();
+        e.step();
+        e.place_order("AAPL".to_string(), -1);
+        e.step();
+        e.place_order("AAPL".to_string(), -1);
+        e.step();
+        assert!(e.acct.fills.len() == 3);
+        assert!(e.acct.fills[1].order_id != e.acct.fills[0].order_id);
+        let trips = e.round_trips();
+        assert!(trips.len() == 1);
+        assert!(trips[0].realized_pnl == Decimal::new(-3, 0));
+        let stats = TradeStats::from_round_trips(&trips);
+        assert!(stats.losses == 1 && stats.average_loss == Some(Decimal::new(-3, 0)));
+    }
+
+    #[test]
+    fn realized_and_unrealized_pnl() {
+        let mut acct = Account::new(Decimal::new(10000, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 4, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -1, cost_basis: Decimal::new(110, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.portfolio["AAPL"].cost_basis == Decimal::new(100, 0));
+        assert!(acct.realized_pnl["AAPL"] == Decimal::new(10, 0));
+        assert!(acct.unrealized_pnl("AAPL", Decimal::new(90, 0)) == Decimal::new(-30, 0));
+
+        assert!(acct.position(Position{asset: "MSFT".to_string(), lots: -2, cost_basis: Decimal::new(50, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "MSFT".to_string(), lots: 2, cost_basis: Decimal::new(45, 0)}, &HashMap::new()).is_ok());
+        assert!(!acct.portfolio.contains_key("MSFT"));
+        assert!(acct.realized_pnl["MSFT"] == Decimal::new(10, 0));
+        assert!(acct.total_realized_pnl(&HashMap::new()).unwrap() == Decimal::new(20, 0));
+
+        let mut prices = HashMap::new();
+        prices.insert("AAPL".to_string(), Decimal::new(105, 0));
+        assert!(acct.total_unrealized_pnl(&prices).unwrap() == Decimal::new(15, 0));
+    }
+
+    #[test]
+    fn engine_pnl_includes_fees() {
+        let mut e = engine_with_prices(&[(10, 10), (12, 12), (15, 15)]);
+        e.set_commission_model(PerTrade(Decimal::new(1, 0)));
+        e.place_order("AAPL".to_string(), 2);
+        e.step();
+        e.place_order("AAPL".to_string(), -1);
+        e.step();
+        assert!(e.acct.realized_pnl["AAPL"] == Decimal::new(2 - 2, 0));
+        assert!(e.unrealized_pnl() == Decimal::new(2, 0));
+        e.step();
+        assert!(e.unrealized_pnl() == Decimal::new(5, 0));
+        assert!(e.equity() == Decimal::new(10000, 0) + e.acct.total_realized_pnl(&e.last_price).unwrap() + e.unrealized_pnl());
+    }
+
+    #[test]
+    fn position_flips_long_to_short() {
+        let mut acct = Account::new(Decimal::new(10000, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 3, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -5, cost_basis: Decimal::new(110, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.portfolio["AAPL"].lots == -2);
+        assert!(acct.portfolio["AAPL"].cost_basis == Decimal::new(110, 0));
+        assert!(acct.realized_pnl["AAPL"] == Decimal::new(30, 0));
+        assert!(acct.cash == Decimal::new(10000 - 300 + 550, 0));
+        assert!(acct.trades.len() == 3);
+
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 2, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.portfolio.is_empty());
+        assert!(acct.realized_pnl["AAPL"] == Decimal::new(50, 0));
+    }
+
+    #[test]
+    fn position_flips_short_to_long() {
+        let mut acct = Account::new(Decimal::new(1000, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -2, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 3, cost_basis: Decimal::new(90, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.portfolio["AAPL"].lots == 1);
+        assert!(acct.portfolio["AAPL"].cost_basis == Decimal::new(90, 0));
+        assert!(acct.realized_pnl["AAPL"] == Decimal::new(20, 0));
+        assert!(acct.cash == Decimal::new(1000 + 200 - 270, 0));
+
+        // a flip the account cannot pay for leaves the position untouched
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -1, cost_basis: Decimal::new(90, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -1, cost_basis: Decimal::new(90, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 20, cost_basis: Decimal::new(90, 0)}, &HashMap::new()).is_err());
+        assert!(acct.portfolio["AAPL"].lots == -1);
+    }
+
+    #[test]
+    fn cash_account_limits_shorts() {
+        let mut e = engine_with_prices(&[(100, 100), (100, 100), (100, 100)]);
+        let id = e.place_order("AAPL".to_string(), -200);
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        assert!(e.acct.orders.is_empty());
+        assert!(e.acct.rejected.len() == 1);
+        assert!(e.acct.rejected[0].id == id);
+        assert!(e.acct.rejected[0].state == OrderState::Rejected);
+        assert!(e.acct.rejected[0].reject_reason.as_ref().unwrap().contains("buying power"));
+        assert!(e.acct.rejected[0].filled_lots == 0);
+        assert!(e.acct.rejected[0].cost_basis.is_none());
+
+        e.place_order("AAPL".to_string(), -100);
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].lots == -100);
+        assert!(e.acct.cash == Decimal::new(20000, 0));
+        assert!(e.acct.buying_power(&e.last_price).unwrap() == Decimal::new(0, 0));
+    }
+
+    #[test]
+    fn leveraged_margin_account() {
+        let mut acct = Account::new(Decimal::new(10000, 0));
+        acct.margin = MarginModel::new(Decimal::new(5, 1), Decimal::new(25, 2));
+        acct.margin.per_asset.insert("ES".to_string(), MarginRequirement{initial: Decimal::new(1, 1), maintenance: Decimal::new(1, 1)});
+        let mut prices = HashMap::new();
+        prices.insert("AAPL".to_string(), Decimal::new(100, 0));
+        prices.insert("ES".to_string(), Decimal::new(100, 0));
+        assert!(acct.buying_power(&prices).unwrap() == Decimal::new(20000, 0));
+
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 150, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.cash == Decimal::new(-5000, 0));
+        assert!(acct.margin_requirement(&prices, false).unwrap() == Decimal::new(7500, 0));
+        assert!(acct.margin_requirement(&prices, true).unwrap() == Decimal::new(3750, 0));
+        assert!(acct.excess_equity(&prices).unwrap() == Decimal::new(2500, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 60, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_err());
+        assert!(acct.position(Position{asset: "ES".to_string(), lots: 250, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.position(Position{asset: "ES".to_string(), lots: 1, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_err());
+        // closing out is always allowed
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -150, cost_basis: Decimal::new(50, 0)}, &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn short_proceeds_held_as_collateral() {
+        let mut acct = Account::new(Decimal::new(10000, 0));
+        acct.margin.short_proceeds = ShortProceeds::Collateral;
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: -10, cost_basis: Decimal::new(100, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.cash == Decimal::new(10000, 0));
+        assert!(acct.short_collateral["AAPL"] == Decimal::new(1000, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 5, cost_basis: Decimal::new(90, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.cash == Decimal::new(10000 + 500 - 450, 0));
+        assert!(acct.short_collateral["AAPL"] == Decimal::new(500, 0));
+        assert!(acct.equity(&HashMap::new()).unwrap() == Decimal::new(10050 + 500 - 500, 0));
+        assert!(acct.position(Position{asset: "AAPL".to_string(), lots: 5, cost_basis: Decimal::new(80, 0)}, &HashMap::new()).is_ok());
+        assert!(acct.short_collateral.is_empty());
+        assert!(acct.cash == Decimal::new(10150, 0));
+    }
+
+    #[test]
+    fn reset_clears_short_collateral_and_orders() {
+        let mut e = engine_with_prices(&[(100, 100), (100, 100), (100, 100)]);
+        e.acct.margin.short_proceeds = ShortProceeds::Collateral;
+        e.place_order("AAPL".to_string(), -10);
+        e.step();
+        e.step();
+        assert!(e.acct.short_collateral["AAPL"] == Decimal::new(1000, 0));
+        e.place_order("AAPL".to_string(), 1);
+        e.reset(10000.);
+        assert!(e.acct.short_collateral.is_empty());
+        assert!(e.acct.orders.is_empty() && e.acct.pending_fills.is_empty());
+        assert!(e.equity() == Decimal::new(10000, 0));
+    }
+
+    /// `short_squeeze` is an engine holding 100 AAPL short in a cash account,
+    /// with prices then rising until equity is below maintenance margin.
+    fn short_squeeze() -> Engine {
+        let mut e = engine_with_prices(&[(100, 100), (100, 100), (140, 140), (160, 160), (170, 170)]);
+        e.place_order("AAPL".to_string(), -100);
+        e.step();
+        e
+    }
+
+    #[test]
+    fn margin_call_notifies() {
+        let mut e = short_squeeze();
+        e.step();
+        assert!(e.margin_calls.is_empty());
+        e.step();
+        assert!(e.margin_calls.len() == 1);
+        assert!(e.margin_calls[0].equity == Decimal::new(6000, 0));
+        assert!(e.margin_calls[0].shortfall() == Decimal::new(8000, 0));
+        assert!(e.margin_calls[0].orders.is_empty());
+        assert!(e.acct.portfolio["AAPL"].lots == -100);
+    }
+
+    #[test]
+    fn margin_call_liquidates_next_tick() {
+        let mut e = short_squeeze();
+        e.margin_call_policy = MarginCallPolicy::LiquidateAll;
+        e.execution = ExecutionTiming::SameTick;
+        e.step();
+        e.step();
+        assert!(e.margin_calls.len() == 1);
+        assert!(e.acct.portfolio["AAPL"].lots == -100);
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        assert!(e.acct.cash == Decimal::new(10000 + 10000 - 16000, 0));
+        assert!(e.margin_calls.len() == 1);
+    }
+
+    struct MarginWatcher {
+        calls: Vec<MarginCall>,
+    }
+
+    impl Strategy for MarginWatcher {
+        fn on_tick(&mut self, _tick: &Tick, _ctx: &mut Context) {}
+
+        fn on_margin_call(&mut self, call: &MarginCall, _ctx: &mut Context) {
+            self.calls.push(call.clone());
+        }
+    }
+
+    #[test]
+    fn margin_call_liquidates_largest_loss_first() {
+        let mut e = engine_with_prices(&[(100, 100), (100, 100), (130, 130), (130, 130)]);
+        e.acct.margin = MarginModel::new(Decimal::new(5, 1), Decimal::new(5, 1));
+        e.margin_call_policy = MarginCallPolicy::LiquidateLargestLoss;
+        assert!(e.acct.position(Position{asset: "MSFT".to_string(), lots: 100, cost_basis: Decimal::new(50, 0)}, &e.last_price).is_ok());
+        e.last_price.insert("MSFT".to_string(), Decimal::new(50, 0));
+        e.place_order("AAPL".to_string(), -100);
+        e.step();
+        let mut s = MarginWatcher{calls: vec![]};
+        e.run(&mut s);
+        assert!(s.calls.len() == 1);
+        assert!(s.calls[0].orders.len() == 1);
+        assert!(e.acct.rejected.is_empty());
+        assert!(!e.acct.portfolio.contains_key("AAPL"));
+        assert!(e.acct.portfolio["MSFT"].lots == 100);
+    }
+
+    #[test]
+    fn margin_call_ranks_losses_in_base_currency() {
+        let mut e = engine_with_prices(&[(100, 100)]);
+        e.acct.margin = MarginModel::new(Decimal::new(5, 1), Decimal::new(5, 1));
+        e.margin_call_policy = MarginCallPolicy::LiquidateLargestLoss;
+        e.register_instrument(Instrument { currency: "JPY".to_string(), ..Instrument::new("SONY".to_string()) });
+        e.last_price.insert("USDJPY".to_string(), Decimal::new(100, 0));
+        e.last_price.insert("AAPL".to_string(), Decimal::new(100, 0));
+        e.last_price.insert("SONY".to_string(), Decimal::new(1000, 0));
+        assert!(e.acct.position(Position{asset: "AAPL".to_string(), lots: 10, cost_basis: Decimal::new(100, 0)}, &e.last_price).is_ok());
+        assert!(e.acct.position(Position{asset: "SONY".to_string(), lots: 100, cost_basis: Decimal::new(1000, 0)}, &e.last_price).is_ok());
+        // AAPL is down 200 USD, SONY 10000 JPY, i.e. 100 USD
+        e.last_price.insert("AAPL".to_string(), Decimal::new(80, 0));
+        e.last_price.insert("SONY".to_string(), Decimal::new(900, 0));
+        e.acct.cash = Decimal::new(-100, 0);
+        assert!(e.equity() == Decimal::new(600, 0));
+
+        let call = e.check_margin_call().unwrap();
+        assert!(call.maintenance_requirement == Decimal::new(850, 0));
+        // closing AAPL frees 400 USD of its 850 USD requirement, which is enough
+        assert!(call.orders.len() == 1);
+        assert!(e.acct.order(call.orders[0]).unwrap().asset == "AAPL");
+    }
+
+    #[test]
+    fn interest_and_borrow_fees_accrue_daily() {
+        let mut e = engine_with_prices(&[(100, 100), (100, 100), (100, 100)]);
+        let start = e.prices.ticks[0].timestamp;
+        e.prices.ticks[1].timestamp = start + chrono::Duration::hours(1);
+        e.prices.ticks[2].timestamp = start + chrono::Duration::days(3);
+        e.financing = Financing{
+            credit_rate: Decimal::new(365, 4),
+            debit_rate: Decimal::new(73, 3),
+            default_borrow_rate: Decimal::new(365, 3),
+            ..Financing::default()
+        };
+        e.place_order("AAPL".to_string(), -10);
+        e.step();
+        e.step();
+        assert!(e.acct.accruals.is_empty());
+        e.step();
+        // 11000 cash at 3.65% for 3 days, and 10 short at 100 at 36.5% for 3 days
+        assert!(e.acct.accruals.len() == 2);
+        assert!(e.acct.accruals[0].kind == AccrualKind::CreditInterest);
+        assert!(e.acct.accruals[0].amount == Decimal::new(33, 1));
+        assert!(e.acct.accruals[1].kind == AccrualKind::BorrowFee("AAPL".to_string()));
+        assert!(e.acct.accruals[1].amount == Decimal::new(-3, 0));
+        assert!(e.acct.accruals[1].days == 3);
+        assert!(e.acct.cash == Decimal::new(110003, 1));
+        assert!(e.acct.realized_pnl["AAPL"] == Decimal::new(-3, 0));
+    }
+
+    #[test]
+    fn debit_interest_on_negative_cash() {
+        let mut acct = Account::new(Decimal::new(-1000, 0));
+        let financing = Financing{debit_rate: Decimal::new(73, 3), ..Financing::default()};
+        acct.accrue(&financing, 5, &HashMap::new(), Utc::now()).unwrap();
+        assert!(acct.accruals[0].kind == AccrualKind::DebitInterest);
+        assert!(acct.cash == Decimal::new(-1001, 0));
+
+        // a debit in a foreign currency is borrowed too, and entered in the base currency
+        acct.balances.insert("EUR".to_string(), Decimal::new(-1000, 0));
+        assert!(acct.accrue(&financing, 5, &HashMap::new(), Utc::now()).is_err());
+        assert!(acct.accruals.len() == 1 && acct.balance("EUR") == Decimal::new(-1000, 0));
+        let mut prices = HashMap::new();
+        prices.insert("EURUSD".to_string(), Decimal::new(12, 1));
+        acct.accrue(&financing, 5, &prices, Utc::now()).unwrap();
+        assert!(acct.accruals.len() == 3);
+        assert!(acct.accruals[2].kind == AccrualKind::DebitInterest);
+        assert!(acct.accruals[2].amount == Decimal::new(-12, 1));
+        assert!(acct.balance("EUR") == Decimal::new(-1001, 0));
+    }
+
+    fn micro_gold() -> Instrument {
+        Instrument{
+            multiplier: Decimal::new(10, 0),
+            tick_size: Some(Decimal::new(1, 1)),
+            ..Instrument::new("MGC".to_string())
+        }
+    }
+
+    /// `gold_engine` is `engine_with_prices` with every tick for MGC.
+    fn gold_engine(prices: &[(i64, i64)]) -> Engine {
+        let mut e = engine_with_prices(prices);
+        for tick in &mut e.prices.ticks {
+            tick.asset = "MGC".to_string();
+        }
+        e
+    }
+
+    #[test]
+    fn contract_multipliers() {
+        let mut e = gold_engine(&[(1800, 1800), (1810, 1810), (1805, 1805)]);
+        e.register_instrument(micro_gold());
+        e.place_order("MGC".to_string(), 2);
+        e.step();
+        assert!(e.acct.rejected.len() == 1);
+        assert!(e.acct.cash == Decimal::new(10000, 0));
+
+        let mut e = gold_engine(&[(180, 180), (181, 181), (179, 179)]);
+        e.register_instrument(micro_gold());
+        e.place_order("MGC".to_string(), 2);
+        e.step();
+        assert!(e.acct.cash == Decimal::new(10000 - 3600, 0));
+        e.step();
+        assert!(e.unrealized_pnl() == Decimal::new(20, 0));
+        assert!(e.equity() == Decimal::new(10020, 0));
+        e.place_order("MGC".to_string(), -2);
+        e.step();
+        assert!(e.acct.realized_pnl["MGC"] == Decimal::new(-20, 0));
+        assert!(e.acct.cash == Decimal::new(9980, 0));
+        assert!(e.round_trips()[0].realized_pnl == Decimal::new(-20, 0));
+
+        // a percentage fee is charged on the full notional of 2 x 180 x 10
+        let mut e = gold_engine(&[(180, 180), (180, 180)]);
+        e.register_instrument(micro_gold());
+        e.set_commission_model(Percentage(Decimal::new(1, 3)));
+        e.place_order("MGC".to_string(), 2);
+        e.step();
+        assert!(e.acct.fees_paid == Decimal::new(36, 1));
+    }
+
+    #[test]
+    fn off_tick_orders_rejected_or_rounded() {
+        let mut e = gold_engine(&[(180, 181), (180, 181)]);
+        let mut lot = micro_gold();
+        lot.lot_size = 5;
+        e.register_instrument(lot);
+        let id = e.submit_order("MGC".to_string(), 5, OrderType::Limit(Decimal::new(17005, 2)));
+        assert!(e.acct.order(id).unwrap().state == OrderState::Rejected);
+        let id = e.place_order("MGC".to_string(), 7);
+        assert!(e.acct.order(id).unwrap().reject_reason.as_ref().unwrap().contains("lot size"));
+        e.step();
+        assert!(e.acct.rejected.len() == 2);
+
+        e.acct.instruments.tick_policy = TickPolicy::Round;
+        let buy = e.submit_order("MGC".to_string(), 7, OrderType::Limit(Decimal::new(17005, 2)));
+        assert!(e.acct.order(buy).unwrap().lots == 5);
+        assert!(e.acct.order(buy).unwrap().order_type == OrderType::Limit(Decimal::new(170, 0)));
+        let sell = e.submit_order("MGC".to_string(), -5, OrderType::Stop(Decimal::new(17005, 2)));
+        assert!(e.acct.order(sell).unwrap().order_type == OrderType::Stop(Decimal::new(170, 0)));
+        assert!(e.modify_order(buy, 5, OrderType::Limit(Decimal::new(17019, 2))).is_ok());
+        assert!(e.acct.order(buy).unwrap().order_type == OrderType::Limit(Decimal::new(1701, 1)));
+        let id = e.place_order("MGC".to_string(), 3);
+        assert!(e.acct.order(id).unwrap().state == OrderState::Rejected);
+    }
+
+    #[test]
+    fn load_instruments() {
+        let toml = Instruments::load(&Path::new("test_resources/instruments.toml")).unwrap();
+        let csv = Instruments::load(&Path::new("test_resources/instruments.csv")).unwrap();
+        let aapl = toml.get("AAPL").unwrap();
+        assert!(aapl.asset_class == AssetClass::Equity);
+        assert!(aapl.exchange.as_deref() == Some("NASDAQ"));
+        assert!(!aapl.shortable);
+        assert!(aapl.multiplier == Decimal::new(1, 0));
+        assert!(aapl.session == csv.get("AAPL").unwrap().session);
+        let mgc = toml.get("MGC").unwrap();
+        assert!(mgc.multiplier == Decimal::new(10, 0));
+        assert!(mgc.tick_size == Some(Decimal::new(1, 1)));
+        assert!(mgc.in_session(Utc.with_ymd_and_hms(2020, 1, 1, 23, 30, 0).unwrap()));
+        assert!(!mgc.in_session(Utc.with_ymd_and_hms(2020, 1, 1, 22, 30, 0).unwrap()));
+        let eurusd = csv.get("EURUSD").unwrap();
+        assert!(eurusd.asset_class == AssetClass::Fx);
+        assert!(eurusd.exchange.is_none());
+        assert!(eurusd.session.is_none());
+        assert!(eurusd.shortable);
+        assert!(Instruments::load(&Path::new("test_resources/ticks.csv")).is_err());
+    }
+
+    #[test]
+    fn orders_fill_only_in_session() {
+        let mut e = engine_with_prices(&[(180, 181), (182, 183), (184, 185)]);
+        e.register_instrument(Instrument {
+            session: Some(TradingSession {
+                start: NaiveTime::from_hms_opt(22, 1, 0).unwrap(),
+                end: NaiveTime::from_hms_opt(22, 2, 0).unwrap(),
+            }),
+            ..Instrument::new("AAPL".to_string())
+        });
+        e.place_order("AAPL".to_string(), 1);
+        e.step();
+        assert!(e.acct.portfolio.is_empty());
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(183, 0));
+        e.place_order("AAPL".to_string(), -1);
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].lots == 1);
+    }
+
+    #[test]
+    fn non_shortable_assets_cannot_go_short() {
+        let mut e = engine_with_prices(&[(180, 181), (182, 183), (184, 185)]);
+        e.register_instrument(Instrument { shortable: false, ..Instrument::new("AAPL".to_string()) });
+        let id = e.place_order("AAPL".to_string(), -1);
+        assert!(e.acct.order(id).unwrap().reject_reason.as_ref().unwrap().contains("not shortable"));
+        e.place_order("AAPL".to_string(), 2);
+        e.step();
+        let id = e.place_order("AAPL".to_string(), -3);
+        assert!(e.acct.order(id).unwrap().state == OrderState::Rejected);
+        e.place_order("AAPL".to_string(), -2);
+        // the working sell already covers the whole position
+        let id = e.place_order("AAPL".to_string(), -1);
+        assert!(e.acct.order(id).unwrap().state == OrderState::Rejected);
+        e.step();
+        assert!(e.acct.portfolio.get("AAPL").map_or(0, |p| p.lots) == 0);
+
+        // growing a working sell past the position is caught when it fills
+        let mut e = engine_with_prices(&[(180, 181), (182, 183), (184, 185)]);
+        e.register_instrument(Instrument { shortable: false, ..Instrument::new("AAPL".to_string()) });
+        e.place_order("AAPL".to_string(), 2);
+        e.step();
+        let id = e.submit_order("AAPL".to_string(), -1, OrderType::Limit(Decimal::new(200, 0)));
+        e.modify_order(id, -3, OrderType::Market).unwrap();
+        e.step();
+        assert!(e.acct.rejected.last().unwrap().reject_reason.as_ref().unwrap().contains("not shortable"));
+        assert!(e.acct.portfolio["AAPL"].lots == 2);
+    }
+
+    #[test]
+    fn fx_rates() {
+        let mut prices = HashMap::new();
+        prices.insert("EURUSD".to_string(), Decimal::new(12, 1));
+        prices.insert("USD/JPY".to_string(), Decimal::new(150, 0));
+        assert!(fx::rate("EUR", "USD", &prices) == Some(Decimal::new(12, 1)));
+        assert!(fx::rate("JPY", "USD", &prices).unwrap().round_dp(6) == Decimal::new(6667, 6));
+        assert!(fx::rate("EUR", "JPY", &prices) == Some(Decimal::new(180, 0)));
+        assert!(fx::convert(Decimal::new(100, 0), "USD", "USD", &prices) == Some(Decimal::new(100, 0)));
+        assert!(fx::rate("GBP", "USD", &prices).is_none());
+    }
+
+    #[test]
+    fn multi_currency_equity() {
+        let mut e = engine_with_prices(&[]);
+        let start = e.time;
+        let tick = |minute: i64, asset: &str, price: i64, scale: u32| Tick {
+            timestamp: start + chrono::Duration::minutes(minute),
+            asset: asset.to_string(),
+            bid: Decimal::new(price, scale),
+            ask: Decimal::new(price, scale),
+        };
+        e.prices.ticks = vec![
+            tick(0, "SAP", 100, 0),
+            tick(1, "EURUSD", 11, 1),
+            tick(2, "SAP", 100, 0),
+            tick(3, "EURUSD", 12, 1),
+            tick(4, "SAP", 110, 0),
+        ];
+        e.register_instrument(Instrument { currency: "EUR".to_string(), ..Instrument::new("SAP".to_string()) });
+
+        e.place_order("SAP".to_string(), 10);
+        e.step();
+        assert!(e.acct.rejected[0].reject_reason.as_ref().unwrap().contains("No FX rate"));
+        e.step();
+        e.place_order("SAP".to_string(), 10);
+        e.step();
+        assert!(e.acct.cash == Decimal::new(10000, 0));
+        assert!(e.acct.balance("EUR") == Decimal::new(-1000, 0));
+        assert!(e.equity() == Decimal::new(10000, 0));
+        e.step();
+        assert!(e.equity() == Decimal::new(10000, 0));
+        e.step();
+        assert!(e.unrealized_pnl() == Decimal::new(120, 0));
+        assert!(e.equity() == Decimal::new(10120, 0));
+
+        assert!(e.acct.fees_paid == Decimal::new(0, 0));
+        assert!(e.acct.to_base(Decimal::new(10, 0), "GBP", &e.last_price).is_err());
+        assert!(e.acct.total_unrealized_pnl(&HashMap::new()).is_err());
+
+        let mut acct = Account::with_base_currency(Decimal::new(10000, 0), "EUR");
+        acct.instruments = e.acct.instruments.clone();
+        assert!(acct.base_currency() == "EUR");
+        assert!(acct.balance("EUR") == Decimal::new(10000, 0));
+        assert!(acct.balance("USD") == Decimal::new(0, 0));
+        let fill = Fill{order_id: OrderId(1), timestamp: start, asset: "AAPL".to_string(), lots: 1, price: Decimal::new(110, 0), fee: Decimal::new(12, 0)};
+        acct.apply_fill(&fill, &e.last_price).unwrap();
+        // the USD fee is converted at the EURUSD rate of 1.2
+        assert!(acct.fees_paid.round_dp(6) == Decimal::new(10, 0));
+        assert!(acct.balance("USD") == Decimal::new(-122, 0));
+    }
+
+    #[test]
+    fn bar_engine_fills_within_bars() {
+        let mut e = init_bar_engine(&"test_resources/bars.csv", 10000);
+        assert!(e.bars.len() == 4 && e.prices.ticks.len() == 4);
+        let high = indicators::MovingAverage::new(2, "high".to_string());
+        e.register_indicator("high".to_string(), Indicator::MovingAverage(high));
+        let volume = indicators::MovingAverage::new(2, "volume".to_string());
+        e.register_indicator("volume".to_string(), Indicator::MovingAverage(volume));
+
+        e.step();
+        assert!(e.equity() == Decimal::new(10000, 0));
+        e.place_order("AAPL".to_string(), 1);
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(103, 0));
+        assert!(e.indicator_values()["high"] == Some(107.5));
+        assert!(e.indicator_values()["volume"] == Some(1250.0));
+        assert!(e.equity() == Decimal::new(10005, 0));
+
+        e.submit_order("AAPL".to_string(), -1, OrderType::Stop(Decimal::new(100, 0)));
+        e.step();
+        assert!(e.acct.realized_pnl["AAPL"] == Decimal::new(-3, 0));
+
+        e.submit_order("AAPL".to_string(), 1, OrderType::Limit(Decimal::new(99, 0)));
+        e.step();
+        assert!(e.acct.portfolio["AAPL"].cost_basis == Decimal::new(98, 0));
+    }
+
+    #[test]
+    fn orders_evaluated_against_bars() {
+        let bar = Bar {
+            timestamp: Utc::now(),
+            asset: "AAPL".to_string(),
+            open: Decimal::new(100, 0),
+            high: Decimal::new(110, 0),
+            low: Decimal::new(95, 0),
+            close: Decimal::new(105, 0),
+            volume: Decimal::new(0, 0),
+        };
+        let mut acct = Account::new(Decimal::new(10000, 0));
+        let mut order = |lots: isize, order_type: OrderType| {
+            let id = acct.submit_order("AAPL".to_string(), lots, order_type);
+            acct.order(id).unwrap().clone()
+        };
+        assert!(order(1, OrderType::Market).evaluate_bar(&bar, &BarFill::Open) == Some(Decimal::new(100, 0)));
+        assert!(order(1, OrderType::Market).evaluate_bar(&bar, &BarFill::Close) == Some(Decimal::new(105, 0)));
+        assert!(order(-1, OrderType::Limit(Decimal::new(108, 0))).evaluate_bar(&bar, &BarFill::Open) == Some(Decimal::new(108, 0)));
+        assert!(order(-1, OrderType::Limit(Decimal::new(111, 0))).evaluate_bar(&bar, &BarFill::Open).is_none());
+        assert!(order(1, OrderType::Stop(Decimal::new(90, 0))).evaluate_bar(&bar, &BarFill::Open) == Some(Decimal::new(100, 0)));
+        assert!(order(-1, OrderType::Stop(Decimal::new(96, 0))).evaluate_bar(&bar, &BarFill::Open) == Some(Decimal::new(96, 0)));
+
+        let mut stop_limit = order(1, OrderType::StopLimit{stop: Decimal::new(106, 0), limit: Decimal::new(107, 0)});
+        assert!(stop_limit.evaluate_bar(&bar, &BarFill::Open) == Some(Decimal::new(106, 0)));
+        let mut gapped = order(1, OrderType::StopLimit{stop: Decimal::new(90, 0), limit: Decimal::new(92, 0)});
+        assert!(gapped.evaluate_bar(&bar, &BarFill::Open).is_none());
+        assert!(gapped.order_type == OrderType::Limit(Decimal::new(92, 0)));
+    }
+
+    struct BuyFirstTick {
+        lots: isize,
+        placed: bool,
+    }
+
+    impl Strategy for BuyFirstTick {
+        fn on_tick(&mut self, _tick: &Tick, ctx: &mut Context) {
+            if !self.placed {
+                ctx.place_order("AAPL", self.lots);
+                self.placed = true;
+            }
+        }
+    }
+
+    #[test]
+    fn same_tick_bars_do_not_fill_inside_seen_bar() {
+        let mut e = init_bar_engine(&"test_resources/bars.csv", 10000);
+        e.execution = ExecutionTiming::SameTick;
+        let result = e.run(&mut BuyFirstTick{lots: 1, placed: false});
+        assert!(result.fills[0].price == Decimal::new(103, 0));
+        assert!(result.fills[0].timestamp == e.prices.ticks[1].timestamp);
+
+        let mut e = init_bar_engine(&"test_resources/bars.csv", 10000);
+        e.execution = ExecutionTiming::SameTick;
+        e.bar_fill = BarFill::Close;
+        let result = e.run(&mut BuyFirstTick{lots: 1, placed: false});
+        assert!(result.fills[0].price == Decimal::new(102, 0));
+        assert!(result.fills[0].timestamp == e.prices.ticks[0].timestamp);
+    }
+
+    fn tick_capture() -> TS {
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap();
+        let tick = |seconds: i64, asset: &str, bid: i64, ask: i64| Tick {
+            timestamp: start + chrono::Duration::seconds(seconds),
+            asset: asset.to_string(),
+            bid: Decimal::new(bid, 0),
+            ask: Decimal::new(ask, 0),
+        };
+        TS { ticks: vec![
+            tick(10, "AAPL", 100, 102),
+            tick(20, "MSFT", 200, 202),
+            tick(50, "AAPL", 104, 106),
+            tick(90, "AAPL", 96, 98),
+            tick(180, "AAPL", 102, 104),
+        ]}
+    }
+
+    #[test]
+    fn resample_time_bars() {
+        let ts = tick_capture();
+        let bars = ts.resample(&BarSpec::Time(chrono::Duration::minutes(1)), &PriceSource::Mid).unwrap();
+        let aapl: Vec<&Bar> = bars.iter().filter(|b| b.asset == "AAPL").collect();
+        assert!(bars.len() == 4 && aapl.len() == 3);
+        assert!(aapl[0].timestamp == Utc.with_ymd_and_hms(2020, 1, 1, 22, 1, 0).unwrap());
+        assert!(aapl[0].open == Decimal::new(101, 0) && aapl[0].close == Decimal::new(105, 0));
+        assert!(aapl[0].high == Decimal::new(105, 0) && aapl[0].low == Decimal::new(101, 0));
+        assert!(aapl[0].volume == Decimal::new(2, 0));
+        assert!(aapl[2].timestamp == Utc.with_ymd_and_hms(2020, 1, 1, 22, 4, 0).unwrap());
+        assert!(bars.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        let bid = ts.resample(&BarSpec::Time(chrono::Duration::days(1)), &PriceSource::Bid).unwrap();
+        let aapl = bid.iter().find(|b| b.asset == "AAPL").unwrap();
+        assert!(aapl.timestamp == Utc.with_ymd_and_hms(2020, 1, 2, 0, 0, 0).unwrap());
+        assert!(aapl.low == Decimal::new(96, 0) && aapl.high == Decimal::new(104, 0));
+        let ask = ts.resample(&BarSpec::Time(chrono::Duration::hours(1)), &PriceSource::Ask).unwrap();
+        assert!(ask.iter().find(|b| b.asset == "AAPL").unwrap().open == Decimal::new(102, 0));
+
+        for d in [chrono::Duration::zero(), chrono::Duration::minutes(-1), chrono::Duration::days(365 * 1000)] {
+            assert!(ts.resample(&BarSpec::Time(d), &PriceSource::Mid) == Err(ResampleError::Interval(d)));
+        }
+    }
+
+    #[test]
+    fn resample_activity_bars() {
+        let ts = tick_capture();
+        let bars: Vec<Bar> = ts.resample(&BarSpec::Ticks(2), &PriceSource::Mid).unwrap().into_iter().filter(|b| b.asset == "AAPL").collect();
+        assert!(bars.len() == 2);
+        assert!(bars[0].timestamp == ts.ticks[2].timestamp && bars[1].close == Decimal::new(103, 0));
+
+        let volumes: Vec<Decimal> = [5, 1, 3, 4, 1].iter().map(|v| Decimal::new(*v, 0)).collect();
+        let bars = ts.resample_with_volume(&volumes, &BarSpec::Volume(Decimal::new(8, 0)), &PriceSource::Mid).unwrap();
+        let aapl: Vec<&Bar> = bars.iter().filter(|b| b.asset == "AAPL").collect();
+        assert!(aapl.len() == 2 && aapl[0].volume == Decimal::new(8, 0) && aapl[1].volume == Decimal::new(5, 0));
+
+        let bars = ts.resample_with_volume(&volumes, &BarSpec::Dollar(Decimal::new(800, 0)), &PriceSource::Mid).unwrap();
+        let aapl: Vec<&Bar> = bars.iter().filter(|b| b.asset == "AAPL").collect();
+        assert!(aapl.len() == 2 && aapl[0].close == Decimal::new(105, 0));
+        let err = ts.resample_with_volume(&volumes[..4], &BarSpec::Ticks(2), &PriceSource::Mid).unwrap_err();
+        assert!(err == ResampleError::Volumes { ticks: 5, volumes: 4 });
+
+        let mut e = bar_engine(ts.resample(&BarSpec::Ticks(2), &PriceSource::Mid).unwrap(), 10000);
+        e.step();
+        e.step();
+        assert!(e.last_price["MSFT"] == Decimal::new(201, 0));
+        assert!(e.last_price["AAPL"] == Decimal::new(105, 0));
+    }
+
+    #[test]
+    fn load_vendor_schema() {
+        let schema: CsvSchema = toml::from_str(r#"
+            delimiter = "|"
+            asset_column = "symbol"
+            bid_column = "best_bid"
+            ask_column = "best_ask"
+            timestamp = { kind = "combined", column = "timestamp", format = "%Y-%m-%dT%H:%M:%S%.f%z" }
+        "#).unwrap();
+        let e = init_engine_with_schema(&"test_resources/vendor_ticks.csv", &schema, 10000);
+        assert!(e.prices.ticks.len() == 2);
+        assert!(e.prices.ticks[0].timestamp == Utc.with_ymd_and_hms(2020, 1, 1, 21, 0, 0).unwrap() + chrono::Duration::milliseconds(500));
+        assert!(e.prices.ticks[1].asset == "EURUSD");
+        assert!(e.prices.ticks[1].ask == Decimal::new(11015, 4));
+        assert!(CsvSchema::default().load(&"test_resources/ticks.csv").unwrap().ticks.len() == init_engine(&"test_resources/ticks.csv", 0).prices.ticks.len());
+    }
+
+    #[test]
+    fn load_epoch_timestamps() {
+        let schema = CsvSchema {
+            timestamp: TimestampColumns::Epoch{column: "t".to_string(), unit: EpochUnit::Seconds},
+            asset_column: None,
+            default_asset: Some("BTC".to_string()),
+            ..CsvSchema::default()
+        };
+        let ts = schema.read("t,Bid,Ask\n1577916000.25,7000,7001\n".as_bytes()).unwrap();
+        assert!(ts.ticks[0].timestamp == Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap() + chrono::Duration::milliseconds(250));
+        assert!(ts.ticks[0].asset == "BTC");
+
+        let nanos = CsvSchema {
+            timestamp: TimestampColumns::Epoch{column: "t".to_string(), unit: EpochUnit::Nanos},
+            ..schema.clone()
+        };
+        assert!(nanos.read("t,Bid,Ask\n1577916000000000001,1,2\n".as_bytes()).unwrap().ticks[0].timestamp.timestamp_subsec_nanos() == 1);
+
+        let err = schema.read("t,Bid,Ask\n1577916000,7000,7001\n1577916001,x,7001\n".as_bytes()).unwrap_err().to_string();
+        assert!(err.contains("Row 3") && err.contains("Bid"));
+        assert!(schema.read("t,Bid\n1,2\n".as_bytes()).unwrap_err().to_string().contains("Ask"));
+        let no_asset = CsvSchema { default_asset: None, ..schema };
+        assert!(no_asset.read("t,Bid,Ask\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn load_local_timestamps() {
+        let chicago = CsvSchema { timezone: Some("America/Chicago".to_string()), ..CsvSchema::default() };
+        let read = |schema: &CsvSchema, date: &str, time: &str| {
+            let csv = format!("Date,Time,Asset,Bid,Ask\n{},{},ES,1,2\n", date, time);
+            schema.read(csv.as_bytes()).map(|ts| ts.ticks[0].timestamp)
+        };
+        let utc = |m: u32, d: u32, h: u32, min: u32| Utc.with_ymd_and_hms(2020, m, d, h, min, 0).unwrap();
+        assert!(read(&chicago, "2020/01/15", "09:30:00").unwrap() == utc(1, 15, 15, 30));
+        assert!(read(&chicago, "2020/07/15", "09:30:00").unwrap() == utc(7, 15, 14, 30));
+        // clocks went forward at 02:00 on 8 March and back at 02:00 on 1 November
+        assert!(read(&chicago, "2020/03/08", "02:30:00").unwrap() == utc(3, 8, 8, 30));
+        assert!(read(&chicago, "2020/11/01", "01:30:00").unwrap() == utc(11, 1, 6, 30));
+        let latest = CsvSchema { ambiguous: AmbiguousTime::Latest, ..chicago.clone() };
+        assert!(read(&latest, "2020/11/01", "01:30:00").unwrap() == utc(11, 1, 7, 30));
+        let strict = CsvSchema { ambiguous: AmbiguousTime::Error, nonexistent: NonexistentTime::Error, ..chicago };
+        assert!(read(&strict, "2020/11/01", "01:30:00").unwrap_err().to_string().contains("ambiguous"));
+        assert!(read(&strict, "2020/03/08", "02:30:00").unwrap_err().to_string().contains("does not exist"));
+
+        let london: CsvSchema = toml::from_str(r#"timezone = "Europe/London""#).unwrap();
+        assert!(read(&london, "2020/06/01", "08:00:00").unwrap() == utc(6, 1, 7, 0));
+        assert!(read(&london, "2020/12/01", "08:00:00").unwrap() == utc(12, 1, 8, 0));
+        let unknown = CsvSchema { timezone: Some("Mars/Olympus".to_string()), ..CsvSchema::default() };
+        assert!(read(&unknown, "2020/06/01", "08:00:00").is_err());
+    }
+
+    #[test]
+    fn validate_test_data() {
+        let load = || CsvSchema::default().load(&"test_resources/ticks.csv").unwrap();
+        let mut ts = load();
+        let report = ts.validate(&Validation::default()).unwrap();
+        assert!(report.ticks == 30);
+        assert!(report.count(&IssueKind::OutOfOrder) == 14);
+        assert!(report.count(&IssueKind::NonPositivePrice) == 1);
+        assert!(report.issues[0].index == 0);
+        assert!(ts.ticks.len() == 30);
+
+        let strict = Validation { policy: ValidationPolicy::Error, ..Validation::default() };
+        let err = load().validate(&strict).unwrap_err();
+        assert!(err.0.issues.len() == 15);
+        assert!(err.to_string().contains("NonPositivePrice"));
+
+        let mut ts = load();
+        let report = ts.validate(&Validation { policy: ValidationPolicy::Sort, ..Validation::default() }).unwrap();
+        assert!(report.sorted && report.dropped == 0);
+        assert!(ts.ticks.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert!(ts.ticks[0].bid == Decimal::new(0, 0) && ts.ticks[15].bid == Decimal::new(1, 0));
+
+        let mut ts = load();
+        let report = ts.validate(&Validation { policy: ValidationPolicy::Drop, ..Validation::default() }).unwrap();
+        assert!(report.dropped == 15 && ts.ticks.len() == 15);
+        assert!(ts.validate(&strict).is_ok());
+    }
+
+    #[test]
+    fn validate_bad_quotes() {
+        let start = Utc.with_ymd_and_hms(2020, 1, 1, 22, 0, 0).unwrap();
+        let tick = |minute: i64, bid: i64, ask: i64| Tick {
+            timestamp: start + chrono::Duration::minutes(minute),
+            asset: "AAPL".to_string(),
+            bid: Decimal::new(bid, 0),
+            ask: Decimal::new(ask, 0),
+        };
+        let mut ts = TS { ticks: vec![tick(0, 100, 101), tick(0, 100, 101), tick(1, 102, 101), tick(2, 150, 151), tick(3, 101, 102), tick(4, -1, 102)] };
+        let validation = Validation { policy: ValidationPolicy::Drop, max_jump: Some(Decimal::new(1, 1)) };
+        let report = ts.validate(&validation).unwrap();
+        let kinds: Vec<IssueKind> = report.issues.iter().map(|i| i.kind.clone()).collect();
+        assert!(kinds == vec![IssueKind::Duplicate, IssueKind::CrossedQuote, IssueKind::OutlierJump, IssueKind::NonPositivePrice]);
+        assert!(report.issues[2].index == 3);
+        assert!(ts.ticks.len() == 2 && ts.ticks[1].timestamp == start + chrono::Duration::minutes(3));
+
+        let mut ts = TS { ticks: vec![tick(0, 100, 101), tick(1, 150, 151), tick(2, 151, 152), tick(3, 150, 151)] };
+        let report = ts.validate(&validation).unwrap();
+        assert!(report.count(&IssueKind::OutlierJump) == 1 && report.issues[0].index == 1);
+        assert!(ts.ticks.len() == 3);
+    }
+
+    #[test]
+    fn engine_construction_errors() {
+        assert!(try_init_engine(&"test_resources/ticks.csv", 10000).is_ok());
+        match try_init_engine(&"test_resources/missing.csv", 10000) {
+            Err(LoadError::Io(e)) => assert!(e.kind() == std::io::ErrorKind::NotFound),
+            other => panic!("expected an IO error, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(try_init_engine(&"test_resources/empty_ticks.csv", 10000), Err(LoadError::Empty)));
+        let empty = try_init_engine_with_schema(&"test_resources/empty_ticks.csv", &CsvSchema::default(), &Validation::default(), 10000);
+        assert!(matches!(empty, Err(LoadError::Empty)));
+        match try_init_engine(&"test_resources/bad_ticks.csv", 10000) {
+            Err(LoadError::Parse { row, column, .. }) => assert!(row == 3 && column.as_deref() == Some("Bid")),
+            other => panic!("expected a parse error, got {:?}", other.map(|_| ())),
+        }
+        assert!(matches!(try_init_bar_engine(&"test_resources/ticks.csv", 10000), Err(LoadError::Parse { .. })));
+        assert!(try_init_bar_engine(&"test_resources/bars.csv", 10000).is_ok());
+
+        let strict = Validation { policy: ValidationPolicy::Error, ..Validation::default() };
+        let err = try_init_engine_with_schema(&"test_resources/ticks.csv", &CsvSchema::default(), &strict, 10000).map(|_| ()).unwrap_err();
+        assert!(matches!(&err, LoadError::Validation(e) if e.0.count(&IssueKind::OutOfOrder) == 14));
+        assert!(err.to_string().starts_with("Data failed validation"));
+        let sort = Validation { policy: ValidationPolicy::Sort, ..Validation::default() };
+        let (e, report) = try_init_engine_with_schema(&"test_resources/ticks.csv", &CsvSchema::default(), &sort, 10000).unwrap();
+        assert!(report.sorted && e.prices.ticks.len() == 30);
+    }
+
+    #[test]
+    fn build_engine_from_config() {
+        let config = EngineConfig::load(&"test_resources/engine.toml").unwrap();
+        assert!(config.data.path == Path::new("test_resources/ticks.csv"));
+        let mut e = config.build().unwrap();
+        assert!(e.acct.cash == Decimal::new(25000, 0));
+        assert!(e.acct.base_currency() == "USD");
+        let eur = EngineConfig::from_toml("cash = 1\nbase_currency = \"EUR\"\n[data]\npath = \"test_resources/ticks.csv\"").unwrap();
+        assert!(eur.build().unwrap().acct.base_currency() == "EUR");
+        assert!(e.execution == ExecutionTiming::Latency(chrono::Duration::milliseconds(500)));
+        assert!(e.max_fill_lots == Some(10));
+        assert!(e.data_quality.as_ref().unwrap().sorted);
+        assert!(e.prices.ticks.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        assert!(e.commissions.commission("AAPL", 2, Decimal::new(100, 0), Decimal::new(1, 0)) == Decimal::new(3, 0));
+        assert!(e.commissions.commission("MGC", 12, Decimal::new(100, 0), e.acct.instruments.multiplier("MGC")) == Decimal::new(12, 0));
+        // the inline AAPL definition replaces the one from the file
+        assert!(e.instrument("AAPL").unwrap().shortable);
+        assert!(e.instrument("AAPL").unwrap().tick_size == Some(Decimal::new(1, 2)));
+        assert!(e.instrument("MGC").unwrap().multiplier == Decimal::new(10, 0));
+        for _ in 0..e.prices.ticks.len() {
+            e.step();
+        }
+        assert!(e.indicator_values()["mom"].is_some());
+    }
+
+    #[test]
+    fn builder_rejects_bad_settings() {
+        let base = || EngineBuilder::new().ticks("test_resources/ticks.csv").cash(Decimal::new(10000, 0));
+        assert!(base().build().unwrap().prices.ticks.len() == 30);
+        let config_error = |b: EngineBuilder| matches!(b.build(), Err(BuildError::Config(_)));
+        assert!(config_error(EngineBuilder::new()));
+        assert!(config_error(base().cash(Decimal::new(-1, 0))));
+        assert!(config_error(base().mode(crate::Mode::Live)));
+        assert!(config_error(base().max_fill_lots(0)));
+        assert!(config_error(base().base_currency("")));
+        let eur = base().base_currency("EUR").build().unwrap();
+        assert!(eur.acct.base_currency() == "EUR" && eur.acct.cash == Decimal::new(10000, 0));
+        let sma = || Indicator::MovingAverage(indicators::MovingAverage::new(2, "price".to_string()));
+        assert!(config_error(base().indicator("a".to_string(), sma()).indicator("a".to_string(), sma())));
+        let orphan = Indicator::Momentum(indicators::Momentum::new(2, "sma".to_string()));
+        assert!(config_error(base().indicator("mom".to_string(), orphan.clone())));
+        assert!(base().indicator("sma".to_string(), sma()).indicator("mom".to_string(), orphan).build().is_ok());
+        assert!(config_error(base().instrument_file("test_resources/ticks.csv")));
+        assert!(matches!(base().ticks("test_resources/missing.csv").build(), Err(BuildError::Load(LoadError::Io(_)))));
+        assert!(matches!(base().data(DataSource::BarSeries(vec![])).build(), Err(BuildError::Load(LoadError::Empty))));
+        let bars = base().bars("test_resources/bars.csv").build().unwrap();
+        assert!(bars.bars.len() == 4);
+        // the data path may come from elsewhere, but building needs one
+        assert!(matches!(EngineConfig::from_toml("cash = 1").unwrap().build(), Err(BuildError::Config(_))));
+    }
+
+    #[test]
+    fn build_strategies_from_registry() {
+        let mut e = init_engine(&"test_resources/ticks.csv", 10000);
+        let params = |pairs: &[(&str, f64)]| pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect::<Params>();
+        for name in strategies::STRATEGIES {
+            assert!(strategies::build(name, &Params::new(), &mut e.clone()).is_ok());
+        }
+        assert!(strategies::build("martingale", &Params::new(), &mut e).is_err());
+        assert!(strategies::build("sma_cross", &params(&[("length", 3.)]), &mut e).is_err());
+        assert!(strategies::build("sma_cross", &params(&[("fast", 5.), ("slow", 3.)]), &mut e).is_err());
+        assert!(strategies::build("momentum", &params(&[("length", 2.5)]), &mut e).is_err());
+        assert!(strategies::build("buy_and_hold", &params(&[("lots", 0.)]), &mut e).is_err());
+        let mut strategy = strategies::build("buy_and_hold", &params(&[("lots", 2.)]), &mut e).unwrap();
+        let result = e.run(&mut strategy);
+        assert!(result.fills.len() == 1);
+        assert!(e.acct.portfolio["AAPL"].lots == 2);
+    }
+
+    #[test]
+    fn run_config_file() {
+        let config = RunConfig::load(&"test_resources/run.toml").unwrap();
+        assert!(config.strategy == "sma_cross");
+        assert!(config.params["slow"] == 4.);
+        assert!(config.engine.data.path == Path::new("test_resources/ticks.csv"));
+        let run = config.run().unwrap();
+        assert!(!run.result.fills.is_empty());
+        assert!(run.engine.acct.fees_paid == Decimal::new(run.result.fills.len() as i64, 0));
+        let summary = run.summary();
+        assert!(summary.starts_with("Strategy        sma_cross\n"));
+        assert!(summary.contains(&format!("Final equity    {}\n", run.result.final_equity)));
+
+        let mut trades = vec![];
+        runner::write_trades(&mut trades, &run.result.trades).unwrap();
+        let trades = String::from_utf8(trades).unwrap();
+        assert!(trades.starts_with("asset,lots,entry_time,exit_time,"));
+        assert!(trades.lines().count() == run.result.trades.len() + 1);
+        let mut equity = vec![];
+        runner::write_equity_curve(&mut equity, &run.engine.equity_curve).unwrap();
+        assert!(String::from_utf8(equity).unwrap().lines().count() == run.engine.equity_curve.len() + 1);
+
+        assert!(matches!(RunConfig::from_toml("cash = 1"), Err(BuildError::Config(_))));
+        let unknown = RunConfig::from_toml("strategy = \"martingale\"\ncash = 1\n[data]\npath = \"test_resources/ticks.csv\"").unwrap();
+        assert!(matches!(unknown.run(), Err(BuildError::Config(_))));
+        let mut missing = config.clone();
+        missing.engine.data.path = "test_resources/missing.csv".into();
+        assert!(matches!(missing.run(), Err(BuildError::Load(_))));
+    }
+
+    #[test]
+    fn run_stays_within_lots() {
+        for execution in ["same_tick", "next_tick"] {
+            let mut config = RunConfig::load(&"test_resources/run.toml").unwrap();
+            config.engine.execution = toml::Value::String(execution.to_string()).try_into().unwrap();
+            let run = config.run().unwrap();
+            let mut held = 0;
+            for fill in &run.result.fills {
+                held += fill.lots;
+                assert!(held.abs() <= 1);
+            }
+            assert!(run.result.trades.iter().all(|t| t.lots.abs() == 1));
+        }
+    }
+
+    #[test]
+    fn merge_tick_streams() {
+        let at = |s: i64, asset: &str| Ok(Tick {
+            timestamp: Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap() + chrono::Duration::seconds(s),
+            asset: asset.to_string(),
+            bid: Decimal::new(1, 0),
+            ask: Decimal::new(1, 0),
+        });
+        let mut merge = TickMerge::new();
+        merge.add(vec![at(0, "A"), at(2, "A"), at(4, "A")].into_iter()).unwrap();
+        merge.add(vec![at(1, "B"), at(2, "B")].into_iter()).unwrap();
+        merge.add(vec![].into_iter()).unwrap();
+        assert!(merge.peek_time() == Some(Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap()));
+        let order: Vec<String> = merge.map(|t| t.unwrap().asset).collect();
+        assert!(order == ["A", "B", "A", "B", "A"]);
+
+        let mut failing = TickMerge::new();
+        failing.add(vec![at(0, "A"), Err(LoadError::Empty), at(5, "A")].into_iter()).unwrap();
+        failing.add(vec![at(1, "B")].into_iter()).unwrap();
+        assert!(failing.next().unwrap().is_ok());
+        assert!(matches!(failing.next(), Some(Err(LoadError::Empty))));
+        assert!(failing.next().is_none());
+    }
+
+    #[test]
+    fn load_tick_directory() {
+        let files = loader::data_files(&"test_resources/archive").unwrap();
+        assert!(files.len() == 4);
+        assert!(files[0].ends_with("AAPL_2020-01-01.csv"));
+        assert!(loader::data_files(&"test_resources/archive/MSFT_*.csv").unwrap().len() == 2);
+        assert!(matches!(loader::data_files(&"test_resources/archive/*.parquet"), Err(LoadError::Io(_))));
+
+        let (ts, report) = loader::load_tick_files(&"test_resources/archive", &CsvSchema::default(), &Validation::default()).unwrap();
+        assert!(report.is_clean());
+        assert!(ts.ticks.len() == 8);
+        assert!(ts.ticks.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+        let assets: Vec<&str> = ts.ticks.iter().map(|t| t.asset.as_str()).collect();
+        assert!(assets == ["AAPL", "MSFT", "AAPL", "MSFT", "MSFT", "AAPL", "AAPL", "AAPL"]);
+        assert!(ts.ticks[5].timestamp == Utc.with_ymd_and_hms(2020, 1, 1, 14, 30, 5).unwrap());
+
+        let e = EngineBuilder::new().tick_files("test_resources/archive/AAPL_*.csv").build().unwrap();
+        assert!(e.prices.ticks.len() == 5);
+        let bad = EngineBuilder::new().tick_files("test_resources/b*_ticks.csv").build();
+        match bad {
+            Err(BuildError::Load(LoadError::File { path, error })) => {
+                assert!(path.ends_with("bad_ticks.csv"));
+                assert!(matches!(*error, LoadError::Parse { row: 3, .. }));
+            }
+            _ => panic!("expected a parse error in bad_ticks.csv"),
+        }
+        let config = EngineConfig::from_toml("cash = 1\n[data]\nkind = \"tick_files\"\npath = \"test_resources/archive\"").unwrap();
+        assert!(config.build().unwrap().prices.ticks.len() == 8);
+    }
 }
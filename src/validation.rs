@@ -0,0 +1,173 @@
+use chrono::prelude::*;
+use hashbrown::{HashMap, HashSet};
+use rust_decimal::prelude::*;
+use serde::Deserialize;
+
+use crate::{Tick, TS};
+
+/// `IssueKind` is a problem found in market data. `OutOfOrder` is a tick
+/// earlier than one before it, `Duplicate` repeats an earlier tick exactly,
+/// `CrossedQuote` has its bid above its ask, `NonPositivePrice` has a bid or
+/// ask at or below zero, and `OutlierJump` moved its asset's mid price by
+/// more than `Validation::max_jump` from both the asset's last good tick and
+/// its previous tick, so a level shift flags only the first tick at the new
+/// level.
+#[derive(Debug, Clone, PartialEq)]
+pub enum IssueKind {
+    OutOfOrder,
+    Duplicate,
+    CrossedQuote,
+    NonPositivePrice,
+    OutlierJump,
+}
+
+/// `Issue` is one problem with the tick at `index` in the data as loaded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issue {
+    pub index: usize,
+    pub timestamp: DateTime<Utc>,
+    pub asset: String,
+    pub kind: IssueKind,
+}
+
+/// `ValidationPolicy` decides what `TS::validate` does about issues.
+/// `Error` fails if there are any. `Sort` puts ticks in time order and drops
+/// duplicates, keeping anything else. `Drop` removes every tick with an
+/// issue. `Warn` leaves the data alone, with the issues only in the report.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationPolicy {
+    Error,
+    Sort,
+    Drop,
+    #[default]
+    Warn,
+}
+
+/// `Validation` holds the `policy` to apply and `max_jump`, the largest
+/// move in an asset's mid price between ticks, as a fraction of the
+/// previous mid, before it is treated as an outlier. Without `max_jump`
+/// no tick is an outlier.
+#[derive(Debug, Clone, PartialEq, Default, Deserialize)]
+#[serde(default)]
+pub struct Validation {
+    pub policy: ValidationPolicy,
+    pub max_jump: Option<Decimal>,
+}
+
+/// `DataQualityReport` lists the `issues` found in `ticks` ticks, whether
+/// they were `sorted`, and how many were `dropped`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DataQualityReport {
+    pub ticks: usize,
+    pub issues: Vec<Issue>,
+    pub sorted: bool,
+    pub dropped: usize,
+}
+
+impl DataQualityReport {
+    pub fn count(&self, kind: &IssueKind) -> usize {
+        self.issues.iter().filter(|i| &i.kind == kind).count()
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// `ValidationError` is returned by `TS::validate` under
+/// `ValidationPolicy::Error`, with the report of what was found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError(pub DataQualityReport);
+
+impl std::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} data issues in {} ticks", self.0.issues.len(), self.0.ticks)?;
+        if let Some(first) = self.0.issues.first() {
+            write!(f, ", first {:?} at tick {} ({} {})", first.kind, first.index, first.asset, first.timestamp)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl TS {
+    /// `validate` checks the ticks for issues and applies `validation`'s
+    /// policy, returning a report of what was found and done.
+    pub fn validate(&mut self, validation: &Validation) -> Result<DataQualityReport, ValidationError> {
+        let issues = find_issues(&self.ticks, validation.max_jump);
+        let mut report = DataQualityReport { ticks: self.ticks.len(), issues, sorted: false, dropped: 0 };
+        match validation.policy {
+            ValidationPolicy::Error if !report.is_clean() => return Err(ValidationError(report)),
+            ValidationPolicy::Error | ValidationPolicy::Warn => {}
+            ValidationPolicy::Sort => {
+                self.ticks.sort_by_key(|t| t.timestamp);
+                let mut seen = HashSet::new();
+                self.ticks.retain(|t| seen.insert(key(t)));
+                report.sorted = true;
+                report.dropped = report.ticks - self.ticks.len();
+            }
+            ValidationPolicy::Drop => {
+                let bad: HashSet<usize> = report.issues.iter().map(|i| i.index).collect();
+                let mut index = 0;
+                self.ticks.retain(|_| {
+                    index += 1;
+                    !bad.contains(&(index - 1))
+                });
+                report.dropped = bad.len();
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn key(tick: &Tick) -> (DateTime<Utc>, String, Decimal, Decimal) {
+    (tick.timestamp, tick.asset.clone(), tick.bid, tick.ask)
+}
+
+fn find_issues(ticks: &[Tick], max_jump: Option<Decimal>) -> Vec<Issue> {
+    let mut issues = vec![];
+    let mut latest: Option<DateTime<Utc>> = None;
+    let mut seen = HashSet::new();
+    let mut last_mid: HashMap<String, Decimal> = HashMap::new();
+    let mut prev_mid: HashMap<String, Decimal> = HashMap::new();
+    for (index, tick) in ticks.iter().enumerate() {
+        let mut found = vec![];
+        if latest.is_some_and(|l| tick.timestamp < l) {
+            found.push(IssueKind::OutOfOrder);
+        }
+        latest = latest.max(Some(tick.timestamp));
+        if !seen.insert(key(tick)) {
+            found.push(IssueKind::Duplicate);
+        }
+        let priced = tick.bid > Decimal::new(0, 0) && tick.ask > Decimal::new(0, 0);
+        if !priced {
+            found.push(IssueKind::NonPositivePrice);
+        }
+        if tick.bid > tick.ask {
+            found.push(IssueKind::CrossedQuote);
+        }
+        if priced && tick.bid <= tick.ask {
+            let mid = (tick.bid + tick.ask) / Decimal::new(2, 0);
+            let away = |from: Option<&Decimal>| match (from, max_jump) {
+                (Some(from), Some(max)) => ((mid - from) / from).abs() > max,
+                _ => false,
+            };
+            let jumped = away(last_mid.get(&tick.asset)) && away(prev_mid.get(&tick.asset));
+            prev_mid.insert(tick.asset.clone(), mid);
+            if jumped {
+                found.push(IssueKind::OutlierJump);
+            } else {
+                last_mid.insert(tick.asset.clone(), mid);
+            }
+        }
+        issues.extend(found.into_iter().map(|kind| Issue {
+            index,
+            timestamp: tick.timestamp,
+            asset: tick.asset.clone(),
+            kind,
+        }));
+    }
+    issues
+}